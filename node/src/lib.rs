@@ -0,0 +1,155 @@
+//! N-API bindings exposing `ruststt` to Node.js.
+//!
+//! Both entry points return Promises; decoding and inference run on the libuv
+//! thread pool so the JS main thread is never blocked.
+
+use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex, OnceLock};
+
+use napi::bindgen_prelude::{AsyncTask, Float32Array};
+use napi::{Env, Task};
+use napi_derive::napi;
+
+const DEFAULT_MODEL: &str = "models/ggml-base.en.bin";
+
+/// Options accepted by `transcribeFile` and `transcribeBuffer`.
+#[napi(object)]
+#[derive(Default)]
+pub struct TranscribeOptions {
+    /// Path to the ggml Whisper model. Defaults to `models/ggml-base.en.bin`.
+    pub model: Option<String>,
+    pub language: Option<String>,
+    pub beam_size: Option<i32>,
+    pub threads: Option<i32>,
+    /// Attach `words` with per-word timing and confidence to every segment.
+    pub word_timestamps: Option<bool>,
+    /// Label every segment with a `speaker` such as `SPEAKER_00`.
+    pub diarize: Option<bool>,
+}
+
+#[napi(object)]
+pub struct Word {
+    pub text: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub probability: f64,
+}
+
+#[napi(object)]
+pub struct Segment {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+    pub no_speech_prob: f64,
+    pub avg_logprob: f64,
+    pub words: Vec<Word>,
+    pub speaker: Option<String>,
+}
+
+/// Loaded models keyed by path, so each `WhisperContext` is kept once per process.
+///
+/// Models load outside the cache lock, so tasks using other (or already
+/// loaded) models are not held up. If two tasks load the same model at once,
+/// the first to finish is cached and the other copy dropped.
+fn transcriber_for(model: &str) -> napi::Result<Arc<ruststt::Transcriber>> {
+    static MODELS: OnceLock<Mutex<HashMap<String, Arc<ruststt::Transcriber>>>> = OnceLock::new();
+    let models = MODELS.get_or_init(Default::default);
+    let lock = || models.lock().map_err(|_| napi::Error::from_reason("model cache poisoned"));
+    if let Some(transcriber) = lock()?.get(model) {
+        return Ok(transcriber.clone());
+    }
+    let transcriber = Arc::new(ruststt::Transcriber::new(model).map_err(to_napi_error)?);
+    Ok(lock()?.entry(model.to_string()).or_insert(transcriber).clone())
+}
+
+fn to_napi_error(e: ruststt::SttError) -> napi::Error {
+    napi::Error::from_reason(e.to_string())
+}
+
+enum Input {
+    File(PathBuf),
+    Samples(Vec<f32>),
+}
+
+pub struct TranscribeTask {
+    input: Input,
+    model: String,
+    options: ruststt::TranscribeOptions,
+}
+
+impl TranscribeTask {
+    fn new(input: Input, opts: Option<TranscribeOptions>) -> Self {
+        let opts = opts.unwrap_or_default();
+        let defaults = ruststt::TranscribeOptions::default();
+        Self {
+            input,
+            model: opts.model.unwrap_or_else(|| DEFAULT_MODEL.to_string()),
+            options: ruststt::TranscribeOptions {
+                language: opts.language.unwrap_or(defaults.language),
+                decoding: ruststt::DecodingOptions {
+                    beam_size: opts.beam_size.unwrap_or(defaults.decoding.beam_size),
+                    ..defaults.decoding
+                },
+                threads: opts.threads.or(defaults.threads),
+                word_timestamps: opts.word_timestamps.unwrap_or(defaults.word_timestamps),
+                diarize: ruststt::DiarizeOptions {
+                    enabled: opts.diarize.unwrap_or(defaults.diarize.enabled),
+                    ..defaults.diarize
+                },
+                ..defaults
+            },
+        }
+    }
+}
+
+impl Task for TranscribeTask {
+    type Output = ruststt::Transcript;
+    type JsValue = Vec<Segment>;
+
+    fn compute(&mut self) -> napi::Result<Self::Output> {
+        let transcriber = transcriber_for(&self.model)?;
+        match &self.input {
+            Input::File(path) => transcriber.transcribe_file(path, &self.options),
+            Input::Samples(samples) => transcriber.transcribe(samples, &self.options),
+        }
+        .map_err(to_napi_error)
+    }
+
+    fn resolve(&mut self, _env: Env, output: Self::Output) -> napi::Result<Self::JsValue> {
+        Ok(output
+            .segments
+            .into_iter()
+            .map(|s| Segment {
+                start_ms: s.start_ms,
+                end_ms: s.end_ms,
+                text: s.text,
+                no_speech_prob: s.no_speech_prob as f64,
+                avg_logprob: s.avg_logprob as f64,
+                words: s
+                    .words
+                    .into_iter()
+                    .map(|w| Word {
+                        text: w.text,
+                        start_ms: w.start_ms,
+                        end_ms: w.end_ms,
+                        probability: w.probability as f64,
+                    })
+                    .collect(),
+                speaker: s.speaker,
+            })
+            .collect())
+    }
+}
+
+/// Transcribes the audio file at `path`.
+#[napi(ts_return_type = "Promise<Segment[]>")]
+pub fn transcribe_file(path: String, opts: Option<TranscribeOptions>) -> AsyncTask<TranscribeTask> {
+    AsyncTask::new(TranscribeTask::new(Input::File(path.into()), opts))
+}
+
+/// Transcribes 16 kHz mono samples.
+#[napi(ts_return_type = "Promise<Segment[]>")]
+pub fn transcribe_buffer(samples: Float32Array, opts: Option<TranscribeOptions>) -> AsyncTask<TranscribeTask> {
+    AsyncTask::new(TranscribeTask::new(Input::Samples(samples.to_vec()), opts))
+}
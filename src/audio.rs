@@ -0,0 +1,477 @@
+//! Loading audio files into the 16 kHz mono `f32` buffers Whisper consumes.
+//!
+//! WAV goes through hound; every other container is decoded natively by
+//! [`crate::decode`]. ffmpeg is only consulted to repair WAV files neither
+//! decoder can parse.
+
+use std::fs::File;
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::chunk::{MemorySource, SampleSource};
+use crate::decode::{self, Container};
+use crate::error::{Result, SttError};
+use crate::repair;
+use crate::resample::{self, ResampleQuality, StreamResampler, WHISPER_SAMPLE_RATE};
+
+/// How multi-channel input is reduced to the mono signal Whisper expects.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ChannelMode {
+    /// Average every channel.
+    #[default]
+    Downmix,
+    /// Keep only the channel at this zero-based index.
+    Select(u16),
+    /// Transcribe every channel on its own and merge the transcripts,
+    /// e.g. agent and customer on the two sides of a call recording.
+    Separate,
+}
+
+impl std::str::FromStr for ChannelMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.eq_ignore_ascii_case("downmix") {
+            return Ok(ChannelMode::Downmix);
+        }
+        if s.eq_ignore_ascii_case("separate") {
+            return Ok(ChannelMode::Separate);
+        }
+        s.parse::<u16>()
+            .map(ChannelMode::Select)
+            .map_err(|_| format!("invalid channel mode '{}' (expected 'downmix', 'separate' or a channel index)", s))
+    }
+}
+
+/// Options controlling how decoded audio is turned into Whisper input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct AudioOptions {
+    pub channels: ChannelMode,
+    /// Filter quality used when the input is not already 16 kHz.
+    pub resample_quality: ResampleQuality,
+    /// Overwrite the source file with the ffmpeg-repaired copy when it fails
+    /// to parse. By default the repair stays in a temporary file.
+    pub repair_in_place: bool,
+}
+
+/// Interleaved `f32` samples at the source rate and channel count.
+#[derive(Clone, Debug, PartialEq)]
+pub struct DecodedAudio {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<f32>,
+}
+
+impl DecodedAudio {
+    /// Number of sample frames (samples per channel).
+    pub fn frames(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+
+    /// Extracts one channel.
+    pub fn channel(&self, index: u16) -> Result<Vec<f32>> {
+        if index >= self.channels {
+            return Err(SttError::InvalidInput(format!(
+                "channel {} requested but the input only has {} channel(s)", index, self.channels
+            )));
+        }
+        Ok(self.samples.iter().skip(index as usize).step_by(self.channels as usize).copied().collect())
+    }
+
+    /// Reduces the audio to a single channel according to `mode`.
+    pub fn to_mono(&self, mode: ChannelMode) -> Result<Vec<f32>> {
+        mix_to_mono(&self.samples, self.channels, mode)
+    }
+
+    /// Mono 16 kHz samples ready for Whisper.
+    pub fn into_whisper_input(self, options: &AudioOptions) -> Result<Vec<f32>> {
+        if self.sample_rate == 0 {
+            return Err(SttError::decode("audio declares a sample rate of 0 Hz"));
+        }
+        let mono = self.to_mono(options.channels)?;
+        if self.sample_rate == WHISPER_SAMPLE_RATE {
+            return Ok(mono);
+        }
+        eprintln!("Resampling {}Hz -> {}Hz ({:?} quality)", self.sample_rate, WHISPER_SAMPLE_RATE, options.resample_quality);
+        Ok(resample::resample(&mono, self.sample_rate, WHISPER_SAMPLE_RATE, options.resample_quality))
+    }
+}
+
+/// Reduces interleaved `samples` of `channels` channels to mono according to `mode`.
+pub(crate) fn mix_to_mono(samples: &[f32], channels: u16, mode: ChannelMode) -> Result<Vec<f32>> {
+    match mode {
+        ChannelMode::Select(index) if index >= channels => Err(SttError::InvalidInput(format!(
+            "channel {} requested but the input only has {} channel(s)", index, channels
+        ))),
+        ChannelMode::Separate => Err(SttError::InvalidInput(
+            "separate channels cannot be mixed into one signal; transcribe the file with Transcriber::transcribe_file".to_string()
+        )),
+        _ if channels == 1 => Ok(samples.to_vec()),
+        ChannelMode::Select(index) => Ok(samples.iter().skip(index as usize).step_by(channels as usize).copied().collect()),
+        ChannelMode::Downmix => {
+            let scale = 1.0 / channels as f32;
+            Ok(samples
+                .chunks_exact(channels as usize)
+                .map(|frame| frame.iter().sum::<f32>() * scale)
+                .collect())
+        }
+    }
+}
+
+/// Reads every sample from `reader` as interleaved `f32` in `[-1.0, 1.0]`.
+///
+/// Integer PCM of any bit depth up to 32 is scaled by its full-scale value;
+/// 32-bit float is passed through.
+pub fn read_wav<R: std::io::Read>(reader: &mut hound::WavReader<R>) -> Result<DecodedAudio> {
+    let spec = reader.spec();
+    check_wav_spec(spec)?;
+
+    let samples: Vec<f32> = match wav_scale(spec)? {
+        Some(scale) => reader.samples::<i32>()
+            .map(|s| s.map(|sample| sample as f32 * scale))
+            .collect::<Result<Vec<f32>, _>>()?,
+        None => reader.samples::<f32>().collect::<Result<Vec<f32>, _>>()?,
+    };
+
+    Ok(DecodedAudio { sample_rate: spec.sample_rate, channels: spec.channels, samples })
+}
+
+/// Rejects headers no audio can be read from.
+fn check_wav_spec(spec: hound::WavSpec) -> Result<()> {
+    if spec.channels == 0 {
+        return Err(SttError::decode("WAV header declares zero channels"));
+    }
+    if spec.sample_rate == 0 {
+        return Err(SttError::decode("WAV header declares a sample rate of 0 Hz"));
+    }
+    Ok(())
+}
+
+/// Full-scale factor for integer WAV samples, or `None` for float samples.
+fn wav_scale(spec: hound::WavSpec) -> Result<Option<f32>> {
+    match (spec.sample_format, spec.bits_per_sample) {
+        (hound::SampleFormat::Int, bits @ 1..=32) => Ok(Some(1.0 / (1u64 << (bits - 1)) as f32)),
+        (hound::SampleFormat::Float, 32) => Ok(None),
+        (format, bits) => Err(SttError::UnsupportedFormat(format!("{}-bit {:?} WAV samples", bits, format))),
+    }
+}
+
+/// Frames read from disk per block by [`WavStream`].
+const WAV_BLOCK_FRAMES: usize = 16 * 1024;
+
+/// Reads a WAV file incrementally as 16 kHz mono samples.
+///
+/// Only one block of source frames and the resampler's history are held in
+/// memory, however long the file is. A file that turns out to be damaged
+/// part-way through, e.g. truncated, is decoded again with [`decode_wav`]'s
+/// fallbacks and picked up where streaming stopped.
+pub struct WavStream {
+    path: PathBuf,
+    options: AudioOptions,
+    reader: hound::WavReader<BufReader<File>>,
+    sample_rate: u32,
+    channels: u16,
+    scale: Option<f32>,
+    resampler: StreamResampler,
+    /// Converted samples not yet handed out.
+    pending: Vec<f32>,
+    finished: bool,
+    /// Samples handed out so far.
+    emitted: usize,
+    /// The rest of the file after a mid-stream read error.
+    fallback: Option<MemorySource>,
+}
+
+impl WavStream {
+    pub fn open(path: impl AsRef<Path>, options: &AudioOptions) -> Result<Self> {
+        let path = path.as_ref();
+        let reader = hound::WavReader::open(path)?;
+        let spec = reader.spec();
+        check_wav_spec(spec)?;
+        let scale = wav_scale(spec)?;
+        if let ChannelMode::Select(index) = options.channels
+            && index >= spec.channels
+        {
+            return Err(SttError::InvalidInput(format!(
+                "channel {} requested but the input only has {} channel(s)", index, spec.channels
+            )));
+        }
+        eprintln!("Sample rate: {}, Channels: {}, Bits per sample: {} ({:?})",
+                 spec.sample_rate, spec.channels, spec.bits_per_sample, spec.sample_format);
+        if spec.sample_rate != WHISPER_SAMPLE_RATE {
+            eprintln!("Resampling {}Hz -> {}Hz ({:?} quality)", spec.sample_rate, WHISPER_SAMPLE_RATE, options.resample_quality);
+        }
+        Ok(Self {
+            path: path.to_path_buf(),
+            options: *options,
+            reader,
+            sample_rate: spec.sample_rate,
+            channels: spec.channels,
+            scale,
+            resampler: StreamResampler::new(spec.sample_rate, WHISPER_SAMPLE_RATE, options.resample_quality),
+            pending: Vec::new(),
+            finished: false,
+            emitted: 0,
+            fallback: None,
+        })
+    }
+
+    fn read_streamed(&mut self, max: usize) -> Result<Vec<f32>> {
+        while self.pending.len() < max && !self.finished {
+            let block = self.read_block()?;
+            if block.is_empty() {
+                self.finished = true;
+                self.pending.extend(self.resampler.flush());
+            } else {
+                let mono = mix_to_mono(&block, self.channels, self.options.channels)?;
+                self.pending.extend(self.resampler.process(&mono));
+            }
+        }
+        let n = max.min(self.pending.len());
+        Ok(self.pending.drain(..n).collect())
+    }
+
+    /// Decodes the whole file with the fallback chain and skips what was already streamed.
+    fn fall_back(&mut self, error: SttError) -> Result<MemorySource> {
+        eprintln!("'{}' failed part-way through ({}); decoding it again with the fallback decoders.", self.path.display(), error);
+        let mut samples = decode_wav(&self.path, self.options.repair_in_place)?.into_whisper_input(&self.options)?;
+        samples.drain(..self.emitted.min(samples.len()));
+        Ok(MemorySource::new(samples))
+    }
+
+    fn read_block(&mut self) -> Result<Vec<f32>> {
+        let count = WAV_BLOCK_FRAMES * self.channels as usize;
+        Ok(match self.scale {
+            Some(scale) => self.reader.samples::<i32>()
+                .take(count)
+                .map(|s| s.map(|sample| sample as f32 * scale))
+                .collect::<Result<Vec<f32>, _>>()?,
+            None => self.reader.samples::<f32>().take(count).collect::<Result<Vec<f32>, _>>()?,
+        })
+    }
+}
+
+impl SampleSource for WavStream {
+    fn read(&mut self, max: usize) -> Result<Vec<f32>> {
+        if let Some(fallback) = &mut self.fallback {
+            return fallback.read(max);
+        }
+        match self.read_streamed(max) {
+            Ok(samples) => {
+                self.emitted += samples.len();
+                Ok(samples)
+            }
+            Err(e @ SttError::AudioDecode { .. }) => {
+                let fallback = self.fall_back(e)?;
+                self.fallback.insert(fallback).read(max)
+            }
+            Err(e) => Err(e),
+        }
+    }
+
+    fn total_samples(&self) -> Option<usize> {
+        Some((self.reader.duration() as u64 * WHISPER_SAMPLE_RATE as u64 / self.sample_rate.max(1) as u64) as usize)
+    }
+}
+
+fn read_wav_file(path: &Path) -> Result<DecodedAudio> {
+    let mut reader = hound::WavReader::open(path)?;
+    let spec = reader.spec();
+    eprintln!("Sample rate: {}, Channels: {}, Bits per sample: {} ({:?})",
+             spec.sample_rate, spec.channels, spec.bits_per_sample, spec.sample_format);
+    read_wav(&mut reader)
+}
+
+/// Bytes of a WAV file searched for the `fmt ` chunk.
+const WAV_HEADER_SCAN: u64 = 64 * 1024;
+
+/// The sample rate in the `fmt ` chunk of a RIFF/WAVE file, read without
+/// validating the rest of the header. Only the start of the file is read.
+fn declared_wav_rate(path: &Path) -> Option<u32> {
+    let mut bytes = Vec::new();
+    File::open(path).ok()?.take(WAV_HEADER_SCAN).read_to_end(&mut bytes).ok()?;
+    if bytes.get(0..4)? != b"RIFF" || bytes.get(8..12)? != b"WAVE" {
+        return None;
+    }
+    let mut offset = 12;
+    while let Some(header) = bytes.get(offset..offset + 8) {
+        let size = u32::from_le_bytes(header[4..8].try_into().ok()?) as usize;
+        if &header[0..4] == b"fmt " {
+            let rate = bytes.get(offset + 12..offset + 16)?;
+            return Some(u32::from_le_bytes(rate.try_into().ok()?));
+        }
+        offset = offset.checked_add(8 + size + size % 2)?;
+    }
+    None
+}
+
+/// Decodes a WAV file, falling back to the native decoder and then to an
+/// ffmpeg repair if hound cannot parse it.
+///
+/// The source file is left untouched unless `repair_in_place` is set.
+pub fn decode_wav(path: impl AsRef<Path>, repair_in_place: bool) -> Result<DecodedAudio> {
+    let path = path.as_ref();
+    let parse_error = match read_wav_file(path) {
+        Ok(audio) => return Ok(audio),
+        Err(e) => e,
+    };
+    eprintln!("'{}' could not be read as WAV ({}).", path.display(), parse_error);
+    // A header that parses but declares 0 Hz is not recoverable, and
+    // symphonia panics on it rather than returning an error.
+    if declared_wav_rate(path) == Some(0) {
+        return Err(parse_error);
+    }
+
+    if let Ok(audio) = decode::decode_file(path) {
+        return Ok(audio);
+    }
+
+    let repaired = repair::repair_with_ffmpeg(path, repair_in_place)?;
+    let audio = read_wav_file(repaired.path()).map_err(|e| {
+        SttError::Repair(format!("the repaired copy of '{}' is still unreadable: {}", path.display(), e))
+    })?;
+    if repair_in_place {
+        repaired.replace(path)?;
+    }
+    Ok(audio)
+}
+
+/// Decodes any supported audio file at its own rate and channel layout.
+fn decode_any(path: &Path, options: &AudioOptions) -> Result<DecodedAudio> {
+    Ok(match decode::sniff_container(path)? {
+        Container::Wav | Container::Unknown => decode_wav(path, options.repair_in_place)?,
+        container => {
+            let audio = decode::decode_file(path)?;
+            eprintln!("Decoded {:?}: Sample rate: {}, Channels: {}", container, audio.sample_rate, audio.channels);
+            audio
+        }
+    })
+}
+
+/// Decodes any supported audio file into 16 kHz mono `f32` samples in `[-1.0, 1.0]`.
+pub fn decode_audio(path: impl AsRef<Path>, options: &AudioOptions) -> Result<Vec<f32>> {
+    let audio_data = decode_any(path.as_ref(), options)?.into_whisper_input(options)?;
+
+    eprintln!("Loaded {} audio samples", audio_data.len());
+    Ok(audio_data)
+}
+
+/// Opens any supported audio file as a [`SampleSource`].
+///
+/// Readable WAV files are streamed block by block; other containers, and WAV
+/// files that need the fallback decoders, are decoded into memory first.
+pub fn open_audio(path: impl AsRef<Path>, options: &AudioOptions) -> Result<Box<dyn SampleSource>> {
+    let path = path.as_ref();
+    if let Container::Wav = decode::sniff_container(path)?
+        && let Ok(stream) = WavStream::open(path, options)
+    {
+        return Ok(Box::new(stream));
+    }
+    Ok(Box::new(MemorySource::new(decode_audio(path, options)?)))
+}
+
+/// Opens every channel of an audio file as its own 16 kHz [`SampleSource`].
+///
+/// Readable WAV files are streamed once per channel; other inputs are decoded
+/// into memory once and split.
+pub fn open_channels(path: impl AsRef<Path>, options: &AudioOptions) -> Result<Vec<Box<dyn SampleSource>>> {
+    let path = path.as_ref();
+    if let Container::Wav = decode::sniff_container(path)?
+        && let Ok(reader) = hound::WavReader::open(path)
+        && reader.spec().channels > 0
+    {
+        return (0..reader.spec().channels)
+            .map(|index| {
+                let stream = WavStream::open(path, &AudioOptions { channels: ChannelMode::Select(index), ..*options })?;
+                Ok(Box::new(stream) as Box<dyn SampleSource>)
+            })
+            .collect();
+    }
+    let audio = decode_any(path, options)?;
+    if audio.sample_rate != WHISPER_SAMPLE_RATE {
+        eprintln!("Resampling {}Hz -> {}Hz ({:?} quality)", audio.sample_rate, WHISPER_SAMPLE_RATE, options.resample_quality);
+    }
+    (0..audio.channels)
+        .map(|index| {
+            let mut samples = audio.channel(index)?;
+            if audio.sample_rate != WHISPER_SAMPLE_RATE {
+                samples = resample::resample(&samples, audio.sample_rate, WHISPER_SAMPLE_RATE, options.resample_quality);
+            }
+            Ok(Box::new(MemorySource::new(samples)) as Box<dyn SampleSource>)
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn scratch_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("ruststt-audio-{}-{}", std::process::id(), name))
+    }
+
+    #[test]
+    fn zero_sample_rate_is_a_decode_error() {
+        let path = scratch_path("zero-rate.wav");
+        let spec = hound::WavSpec { channels: 1, sample_rate: 16_000, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for _ in 0..1_600 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+        // The sample and byte rate fields of the canonical 44-byte header.
+        let mut bytes = std::fs::read(&path).unwrap();
+        bytes[24..28].copy_from_slice(&0u32.to_le_bytes());
+        bytes[28..32].copy_from_slice(&0u32.to_le_bytes());
+        std::fs::write(&path, bytes).unwrap();
+
+        let streamed = open_audio(&path, &AudioOptions::default()).err();
+        let decoded = decode_audio(&path, &AudioOptions::default()).err();
+        std::fs::remove_file(&path).unwrap();
+        assert!(matches!(streamed, Some(SttError::AudioDecode { .. })), "{streamed:?}");
+        assert!(matches!(decoded, Some(SttError::AudioDecode { .. })), "{decoded:?}");
+    }
+
+    #[test]
+    fn truncated_wav_falls_back_without_losing_streamed_audio() {
+        let path = scratch_path("truncated.wav");
+        let spec = hound::WavSpec { channels: 1, sample_rate: 16_000, bits_per_sample: 16, sample_format: hound::SampleFormat::Int };
+        let source: Vec<i16> = (0..32_000).map(|i| ((i % 200) as i16 - 100) * 100).collect();
+        let mut writer = hound::WavWriter::create(&path, spec).unwrap();
+        for &sample in &source {
+            writer.write_sample(sample).unwrap();
+        }
+        writer.finalize().unwrap();
+        // Cut the last half second; the header still declares two seconds.
+        let length = std::fs::metadata(&path).unwrap().len();
+        std::fs::OpenOptions::new().write(true).open(&path).unwrap().set_len(length - 16_000).unwrap();
+
+        let mut stream = open_audio(&path, &AudioOptions::default()).unwrap();
+        let mut samples = Vec::new();
+        loop {
+            let block = stream.read(16_000).unwrap();
+            if block.is_empty() {
+                break;
+            }
+            samples.extend(block);
+        }
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(samples.len(), 24_000);
+        for (got, want) in samples.iter().zip(&source) {
+            assert!((got - *want as f32 / 32_768.0).abs() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn mix_to_mono_selects_and_averages() {
+        let stereo = [0.5, -0.5, 1.0, 0.0];
+        assert_eq!(mix_to_mono(&stereo, 2, ChannelMode::Downmix).unwrap(), vec![0.0, 0.5]);
+        assert_eq!(mix_to_mono(&stereo, 2, ChannelMode::Select(1)).unwrap(), vec![-0.5, 0.0]);
+        assert!(mix_to_mono(&stereo, 2, ChannelMode::Select(2)).is_err());
+        assert!(mix_to_mono(&stereo, 2, ChannelMode::Separate).is_err());
+    }
+}
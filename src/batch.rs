@@ -0,0 +1,114 @@
+//! Transcribing many files with one loaded model.
+//!
+//! The model is loaded once; a fixed pool of worker threads, each owning its
+//! own `WhisperState`, pulls inputs from a shared queue.
+
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::{Result, SttError};
+use crate::transcriber::{TranscribeOptions, Transcriber, Transcript};
+
+/// File extensions picked up when a directory is given.
+pub const AUDIO_EXTENSIONS: &[&str] = &["wav", "mp3", "flac", "ogg", "oga", "opus", "m4a", "mp4", "aac"];
+
+/// The outcome of transcribing one batch input.
+pub struct BatchItem<'a> {
+    pub input: &'a Path,
+    pub transcript: Result<Transcript>,
+    /// Wall time spent decoding and transcribing this input.
+    pub elapsed: Duration,
+}
+
+/// Expands a directory (its audio files, non-recursively), a glob pattern
+/// or a single file into a sorted list of inputs.
+pub fn collect_inputs(spec: &str) -> Result<Vec<PathBuf>> {
+    let path = Path::new(spec);
+    let mut inputs: Vec<PathBuf> = if path.is_dir() {
+        std::fs::read_dir(path)?
+            .map(|entry| entry.map(|e| e.path()))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter(|p| p.is_file() && has_audio_extension(p))
+            .collect()
+    } else if path.is_file() {
+        vec![path.to_path_buf()]
+    } else {
+        glob::glob(spec)
+            .map_err(|e| SttError::InvalidInput(format!("invalid glob pattern '{}': {}", spec, e)))?
+            .filter_map(|entry| match entry {
+                Ok(p) => Some(p),
+                Err(e) => {
+                    eprintln!("Skipping '{}': {}", e.path().display(), e.error());
+                    None
+                }
+            })
+            .filter(|p| p.is_file())
+            .collect()
+    };
+    if inputs.is_empty() {
+        return Err(SttError::InvalidInput(format!("'{}' matched no audio files", spec)));
+    }
+    inputs.sort();
+    Ok(inputs)
+}
+
+fn has_audio_extension(path: &Path) -> bool {
+    path.extension()
+        .and_then(|e| e.to_str())
+        .is_some_and(|e| AUDIO_EXTENSIONS.iter().any(|known| known.eq_ignore_ascii_case(e)))
+}
+
+/// Concurrent states used when no job count is given: one per four physical
+/// cores, which keeps every core busy without paying for a state per core.
+pub fn default_jobs() -> usize {
+    (num_cpus::get_physical() / 4).max(1)
+}
+
+/// Inference threads for each of `jobs` concurrent states when the physical
+/// cores are shared out evenly.
+pub fn split_threads(jobs: usize) -> i32 {
+    (num_cpus::get_physical() / jobs.max(1)).max(1) as i32
+}
+
+/// Transcribes `inputs` on `jobs` concurrent states, calling `on_done` from
+/// the worker thread as each input finishes. A failing input does not stop
+/// the others.
+///
+/// When `options.threads` is `None` the physical cores are split between the
+/// states (see [`split_threads`]) rather than each state claiming all of them.
+pub fn transcribe_batch<F>(transcriber: &Transcriber, inputs: &[PathBuf], options: &TranscribeOptions, jobs: usize, on_done: F) -> Result<()>
+where
+    F: Fn(BatchItem<'_>) + Sync,
+{
+    let jobs = jobs.clamp(1, inputs.len().max(1));
+    let split;
+    let options = if options.threads.is_none() {
+        split = TranscribeOptions { threads: Some(split_threads(jobs)), ..options.clone() };
+        &split
+    } else {
+        options
+    };
+    // States are created up front so an out-of-memory context fails the
+    // whole batch immediately instead of every input one by one.
+    let states = (0..jobs)
+        .map(|_| transcriber.context().create_state())
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let next = AtomicUsize::new(0);
+    let (next, on_done) = (&next, &on_done);
+    thread::scope(|scope| {
+        for mut state in states {
+            scope.spawn(move || {
+                while let Some(input) = inputs.get(next.fetch_add(1, Ordering::Relaxed)) {
+                    let start = Instant::now();
+                    let transcript = transcriber.transcribe_file_with_state(&mut state, input, options);
+                    on_done(BatchItem { input, transcript, elapsed: start.elapsed() });
+                }
+            });
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,250 @@
+//! Merging the transcripts of separately transcribed channels.
+//!
+//! Each channel of a call recording usually carries one party, but every
+//! microphone also picks up the other side faintly, and Whisper happily
+//! transcribes that bleed. While a channel is transcribed its loudness is
+//! recorded in 100 ms blocks; when two channels produce overlapping segments
+//! with the same words, only the one from the louder channel is kept.
+
+use crate::chunk::SampleSource;
+use crate::error::Result;
+use crate::resample::WHISPER_SAMPLE_RATE;
+use crate::transcriber::{Segment, Transcript};
+
+/// Samples per loudness block (100 ms).
+const BLOCK: usize = WHISPER_SAMPLE_RATE as usize / 10;
+/// Segments overlapping by at least this share of the shorter one may be duplicates.
+const MIN_OVERLAP: f32 = 0.5;
+/// Share of the shorter segment's words that must recur in the other.
+const MIN_SHARED_WORDS: f32 = 0.6;
+
+/// Label for channel `index` when no name was given.
+fn channel_label(index: usize) -> String {
+    format!("CHANNEL_{}", index)
+}
+
+/// Passes samples through while recording their mean power per 100 ms block.
+pub(crate) struct LevelMeter<'a> {
+    source: &'a mut dyn SampleSource,
+    blocks: Vec<f32>,
+    sum: f32,
+    count: usize,
+}
+
+impl<'a> LevelMeter<'a> {
+    pub(crate) fn new(source: &'a mut dyn SampleSource) -> Self {
+        Self { source, blocks: Vec::new(), sum: 0.0, count: 0 }
+    }
+
+    pub(crate) fn finish(mut self) -> Levels {
+        if self.count > 0 {
+            self.blocks.push(self.sum / self.count as f32);
+        }
+        Levels(self.blocks)
+    }
+}
+
+impl SampleSource for LevelMeter<'_> {
+    fn read(&mut self, max: usize) -> Result<Vec<f32>> {
+        let samples = self.source.read(max)?;
+        for sample in &samples {
+            self.sum += sample * sample;
+            self.count += 1;
+            if self.count == BLOCK {
+                self.blocks.push(self.sum / BLOCK as f32);
+                self.sum = 0.0;
+                self.count = 0;
+            }
+        }
+        Ok(samples)
+    }
+
+    fn total_samples(&self) -> Option<usize> {
+        self.source.total_samples()
+    }
+}
+
+/// Loudness envelope of one channel.
+pub(crate) struct Levels(Vec<f32>);
+
+impl Levels {
+    /// Mean power between `start_ms` and `end_ms`.
+    fn power(&self, start_ms: i64, end_ms: i64) -> f32 {
+        let first = ((start_ms.max(0) / 100) as usize).min(self.0.len());
+        let last = (((end_ms.max(0) + 99) / 100) as usize).clamp(first, self.0.len());
+        let blocks = &self.0[first..last];
+        if blocks.is_empty() { 0.0 } else { blocks.iter().sum::<f32>() / blocks.len() as f32 }
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split_whitespace()
+        .map(|w| w.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect::<String>())
+        .filter(|w| !w.is_empty())
+        .collect()
+}
+
+/// Whether `a` and `b` look like the same speech heard on two channels.
+fn same_speech(a: &Segment, a_words: &[String], b: &Segment, b_words: &[String]) -> bool {
+    let overlap = a.end_ms.min(b.end_ms) - a.start_ms.max(b.start_ms);
+    let shorter = (a.end_ms - a.start_ms).min(b.end_ms - b.start_ms).max(1);
+    if (overlap as f32) < MIN_OVERLAP * shorter as f32 {
+        return false;
+    }
+    let (short, long) = if a_words.len() <= b_words.len() { (a_words, b_words) } else { (b_words, a_words) };
+    if short.is_empty() {
+        return false;
+    }
+    let shared = short.iter().filter(|w| long.contains(w)).count();
+    shared as f32 >= MIN_SHARED_WORDS * short.len() as f32
+}
+
+/// Merges per-channel transcripts into one time-ordered transcript, labelling
+/// each segment with its channel and dropping crosstalk duplicates.
+///
+/// A segment already labelled by diarization keeps its speaker after the
+/// channel label, as in `agent/SPEAKER_01`.
+pub(crate) fn merge(channels: Vec<(Transcript, Levels)>, labels: &[String]) -> Transcript {
+    let run = channels.first().map(|(t, _)| t.run.clone()).unwrap_or_default();
+    let levels: Vec<&Levels> = channels.iter().map(|(_, l)| l).collect();
+
+    let mut all: Vec<(usize, Segment)> = Vec::new();
+    for (index, (transcript, _)) in channels.iter().enumerate() {
+        let label = labels.get(index).cloned().unwrap_or_else(|| channel_label(index));
+        for segment in &transcript.segments {
+            let mut segment = segment.clone();
+            segment.channel = Some(index as u16);
+            segment.speaker = Some(match segment.speaker.take() {
+                Some(speaker) => format!("{}/{}", label, speaker),
+                None => label.clone(),
+            });
+            all.push((index, segment));
+        }
+    }
+    all.sort_by_key(|(index, segment)| (segment.start_ms, *index));
+
+    // Of two overlapping segments with the same words on different channels,
+    // the one from the channel that was quieter over that span is bleed.
+    let texts: Vec<Vec<String>> = all.iter().map(|(_, segment)| words(&segment.text)).collect();
+    let mut bleed = vec![false; all.len()];
+    for i in 0..all.len() {
+        let (channel, segment) = &all[i];
+        for j in i + 1..all.len() {
+            let (other, candidate) = &all[j];
+            if candidate.start_ms >= segment.end_ms {
+                break;
+            }
+            if other == channel || !same_speech(segment, &texts[i], candidate, &texts[j]) {
+                continue;
+            }
+            let start = segment.start_ms.max(candidate.start_ms);
+            let end = segment.end_ms.min(candidate.end_ms);
+            let (mine, theirs) = (levels[*channel].power(start, end), levels[*other].power(start, end));
+            if mine < theirs {
+                bleed[i] = true;
+            } else if theirs < mine {
+                bleed[j] = true;
+            }
+        }
+    }
+    let dropped = bleed.iter().filter(|&&b| b).count();
+    if dropped > 0 {
+        eprintln!("Dropped {} crosstalk segment(s)", dropped);
+    }
+
+    let segments = all
+        .into_iter()
+        .zip(bleed)
+        .filter(|(_, bleed)| !bleed)
+        .map(|((_, segment), _)| segment)
+        .collect();
+    Transcript { segments, run }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::MemorySource;
+
+    fn segment(start_ms: i64, end_ms: i64, text: &str) -> Segment {
+        Segment { start_ms, end_ms, text: text.to_string(), ..Default::default() }
+    }
+
+    fn transcript(segments: Vec<Segment>) -> Transcript {
+        Transcript { segments, ..Default::default() }
+    }
+
+    /// A loudness envelope of 100 ms blocks: `loud` power over `[from_ms, to_ms)`, 0.001 elsewhere.
+    fn levels(total_ms: i64, loud: f32, from_ms: i64, to_ms: i64) -> Levels {
+        Levels((0..total_ms / 100).map(|b| if (from_ms..to_ms).contains(&(b * 100)) { loud } else { 0.001 }).collect())
+    }
+
+    fn speech(merged: &Transcript) -> Vec<(Option<u16>, &str, &str)> {
+        merged.segments.iter().map(|s| (s.channel, s.speaker.as_deref().unwrap_or(""), s.text.as_str())).collect()
+    }
+
+    #[test]
+    fn power_averages_the_blocks_in_a_span() {
+        let levels = Levels(vec![1.0, 3.0, 5.0]);
+        assert_eq!(levels.power(0, 200), 2.0);
+        assert_eq!(levels.power(150, 250), 4.0);
+        assert_eq!(levels.power(-100, 10_000), 3.0);
+        assert_eq!(levels.power(500, 800), 0.0);
+    }
+
+    #[test]
+    fn level_meter_records_mean_power_per_block() {
+        let mut source = MemorySource::new([vec![0.5; BLOCK], vec![0.1; BLOCK / 2]].concat());
+        let mut meter = LevelMeter::new(&mut source);
+        while !meter.read(700).unwrap().is_empty() {}
+        let Levels(blocks) = meter.finish();
+        assert_eq!(blocks.len(), 2);
+        assert!((blocks[0] - 0.25).abs() < 1e-5 && (blocks[1] - 0.01).abs() < 1e-5, "{blocks:?}");
+    }
+
+    #[test]
+    fn same_speech_needs_time_overlap_and_shared_words() {
+        let check = |a: &Segment, b: &Segment| same_speech(a, &words(&a.text), b, &words(&b.text));
+        let said = segment(1_000, 3_000, " Hello, how are you?");
+        assert!(check(&said, &segment(1_200, 3_100, " hello how are you doing")));
+        assert!(!check(&said, &segment(2_500, 5_000, " hello how are you")));
+        assert!(!check(&said, &segment(1_000, 3_000, " fine thanks and you")));
+        assert!(!check(&said, &segment(1_000, 3_000, " ...")));
+    }
+
+    #[test]
+    fn merge_keeps_the_louder_channel_of_a_duplicate() {
+        let agent = transcript(vec![segment(0, 2_000, " Hello, how are you?"), segment(4_000, 5_000, " Great.")]);
+        let customer = transcript(vec![segment(100, 1_900, " hello how are you"), segment(2_500, 3_500, " Fine, thanks.")]);
+        let merged = merge(
+            vec![(agent, levels(6_000, 0.5, 0, 2_000)), (customer, levels(6_000, 0.5, 2_000, 4_000))],
+            &["agent".to_string()],
+        );
+        assert_eq!(
+            speech(&merged),
+            vec![(Some(0), "agent", " Hello, how are you?"), (Some(1), "CHANNEL_1", " Fine, thanks."), (Some(0), "agent", " Great.")]
+        );
+    }
+
+    #[test]
+    fn merge_drops_the_quieter_channel_whichever_it_is() {
+        let first = transcript(vec![segment(0, 2_000, " see you tomorrow")]);
+        let second = transcript(vec![segment(50, 2_000, " See you tomorrow.")]);
+        let merged = merge(vec![(first, levels(2_000, 0.01, 0, 2_000)), (second, levels(2_000, 0.4, 0, 2_000))], &[]);
+        assert_eq!(speech(&merged), vec![(Some(1), "CHANNEL_1", " See you tomorrow.")]);
+    }
+
+    #[test]
+    fn merge_keeps_overlapping_segments_with_different_words() {
+        let mut diarized = segment(0, 2_000, " Can you hear me?");
+        diarized.speaker = Some("SPEAKER_01".to_string());
+        let merged = merge(
+            vec![
+                (transcript(vec![diarized]), levels(2_000, 0.5, 0, 2_000)),
+                (transcript(vec![segment(500, 1_500, " Yes.")]), levels(2_000, 0.001, 0, 0)),
+            ],
+            &["agent".to_string(), "customer".to_string()],
+        );
+        assert_eq!(speech(&merged), vec![(Some(0), "agent/SPEAKER_01", " Can you hear me?"), (Some(1), "customer", " Yes.")]);
+    }
+}
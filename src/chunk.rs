@@ -0,0 +1,196 @@
+//! Fixed-size windows over long recordings.
+//!
+//! Files are pulled through a [`SampleSource`] one window at a time, so memory
+//! stays bounded by the window length rather than the recording length.
+//! Neighbouring windows overlap; each keeps the segments centred on its side
+//! of the overlap midpoint, and words repeated across the cut are dropped.
+
+use serde::Serialize;
+
+use crate::error::Result;
+use crate::resample::WHISPER_SAMPLE_RATE;
+use crate::transcriber::Segment;
+
+/// Window layout for chunked transcription.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct ChunkOptions {
+    /// Audio decoded per inference call.
+    pub chunk_ms: u32,
+    /// Audio shared by consecutive chunks; capped at half a chunk.
+    pub overlap_ms: u32,
+}
+
+impl Default for ChunkOptions {
+    fn default() -> Self {
+        Self { chunk_ms: 30_000, overlap_ms: 2_000 }
+    }
+}
+
+impl ChunkOptions {
+    /// Chunk and overlap lengths in 16 kHz samples.
+    pub(crate) fn samples(&self) -> (usize, usize) {
+        let chunk = (self.chunk_ms as u64 * WHISPER_SAMPLE_RATE as u64 / 1000).max(WHISPER_SAMPLE_RATE as u64) as usize;
+        let overlap = (self.overlap_ms as u64 * WHISPER_SAMPLE_RATE as u64 / 1000) as usize;
+        (chunk, overlap.min(chunk / 2))
+    }
+}
+
+/// Incremental supply of 16 kHz mono samples.
+pub trait SampleSource {
+    /// Returns up to `max` further samples; an empty result means the input has ended.
+    fn read(&mut self, max: usize) -> Result<Vec<f32>>;
+
+    /// Total length of the input in 16 kHz samples, when known up front.
+    fn total_samples(&self) -> Option<usize> {
+        None
+    }
+}
+
+/// A [`SampleSource`] over audio already in memory.
+pub struct MemorySource {
+    samples: Vec<f32>,
+    position: usize,
+}
+
+impl MemorySource {
+    pub fn new(samples: Vec<f32>) -> Self {
+        Self { samples, position: 0 }
+    }
+}
+
+impl SampleSource for MemorySource {
+    fn read(&mut self, max: usize) -> Result<Vec<f32>> {
+        let end = (self.position + max).min(self.samples.len());
+        let out = self.samples[self.position..end].to_vec();
+        self.position = end;
+        Ok(out)
+    }
+
+    fn total_samples(&self) -> Option<usize> {
+        Some(self.samples.len())
+    }
+}
+
+/// Collects the segments of consecutive overlapping chunks into one timeline.
+#[derive(Default)]
+pub(crate) struct Stitcher {
+    segments: Vec<Segment>,
+    /// Segments centred before this time belong to an earlier chunk.
+    cut_ms: i64,
+}
+
+impl Stitcher {
+    /// Adds the segments of one chunk, already on the absolute timeline,
+    /// keeping those centred before `next_cut_ms` (`None` for the last chunk).
+    pub(crate) fn push(&mut self, segments: Vec<Segment>, next_cut_ms: Option<i64>) {
+        let end = next_cut_ms.unwrap_or(i64::MAX);
+        for mut segment in segments {
+            let middle = (segment.start_ms + segment.end_ms) / 2;
+            if middle < self.cut_ms || middle >= end {
+                continue;
+            }
+            if let Some(previous) = self.segments.last()
+                && !trim_repeated_words(previous, &mut segment)
+            {
+                continue;
+            }
+            self.segments.push(segment);
+        }
+        self.cut_ms = end;
+    }
+
+    /// The segments kept so far.
+    pub(crate) fn segments(&self) -> &[Segment] {
+        &self.segments
+    }
+
+    /// The text of the most recent segments, at least `min_chars` long when available.
+    pub(crate) fn recent_text(&self, min_chars: usize) -> String {
+        let mut len = 0;
+        let start = self.segments.iter().rposition(|segment| {
+            len += segment.text.len() + 1;
+            len >= min_chars
+        }).unwrap_or(0);
+        self.segments[start..].iter().map(|s| s.text.as_str()).collect::<Vec<_>>().join(" ")
+    }
+
+    pub(crate) fn finish(self) -> Vec<Segment> {
+        self.segments
+    }
+}
+
+fn normalize(word: &str) -> String {
+    word.chars().filter(|c| c.is_alphanumeric()).flat_map(char::to_lowercase).collect()
+}
+
+/// Removes the leading words of `next` that repeat the end of `previous` when
+/// the two overlap in time. Returns `false` if nothing of `next` is left.
+fn trim_repeated_words(previous: &Segment, next: &mut Segment) -> bool {
+    if next.start_ms >= previous.end_ms {
+        return true;
+    }
+    let before: Vec<String> = previous.text.split_whitespace().map(normalize).collect();
+    let after: Vec<&str> = next.text.split_whitespace().collect();
+    let after_normalized: Vec<String> = after.iter().map(|w| normalize(w)).collect();
+
+    let repeated = (1..=before.len().min(after.len()))
+        .rev()
+        .find(|&k| before[before.len() - k..] == after_normalized[..k])
+        .unwrap_or(0);
+    if repeated == 0 {
+        return true;
+    }
+    if repeated == after.len() {
+        return false;
+    }
+    next.text = after[repeated..].join(" ");
+    next.start_ms = next.start_ms.max(previous.end_ms).min(next.end_ms);
+    next.words.retain(|word| word.end_ms > previous.end_ms);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(start_ms: i64, end_ms: i64, text: &str) -> Segment {
+        Segment { start_ms, end_ms, text: text.to_string(), ..Default::default() }
+    }
+
+    fn texts(segments: &[Segment]) -> Vec<&str> {
+        segments.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    #[test]
+    fn push_keeps_segments_centred_on_each_side_of_the_cut() {
+        let mut stitcher = Stitcher::default();
+        stitcher.push(vec![segment(0, 10_000, "first"), segment(28_000, 30_000, "cut off")], Some(29_000));
+        stitcher.push(vec![segment(27_000, 28_000, "seen"), segment(28_000, 30_000, "whole"), segment(30_000, 40_000, "second")], None);
+        assert_eq!(texts(stitcher.segments()), vec!["first", "whole", "second"]);
+    }
+
+    #[test]
+    fn push_drops_words_repeated_across_the_overlap() {
+        let mut stitcher = Stitcher::default();
+        stitcher.push(vec![segment(20_000, 28_900, " and then we went home")], Some(29_000));
+        stitcher.push(vec![segment(28_000, 32_000, " Went home, after dinner."), segment(32_000, 34_000, " Bye.")], None);
+        let segments = stitcher.finish();
+        assert_eq!(texts(&segments), vec![" and then we went home", "after dinner.", " Bye."]);
+        assert_eq!(segments[1].start_ms, 28_900);
+    }
+
+    #[test]
+    fn trim_repeated_words_only_applies_to_overlapping_segments() {
+        let previous = segment(0, 1_000, "see you");
+        let mut later = segment(1_000, 2_000, "you too");
+        assert!(trim_repeated_words(&previous, &mut later));
+        assert_eq!(later.text, "you too");
+
+        let mut overlapping = segment(900, 2_000, "you too");
+        assert!(trim_repeated_words(&previous, &mut overlapping));
+        assert_eq!((overlapping.text.as_str(), overlapping.start_ms), ("too", 1_000));
+
+        let mut repeat = segment(500, 1_200, "See you.");
+        assert!(!trim_repeated_words(&previous, &mut repeat));
+    }
+}
@@ -0,0 +1,167 @@
+//! Watching and stopping a transcription while it runs.
+
+use std::fmt;
+use std::sync::atomic::{AtomicBool, AtomicI64, Ordering};
+use std::sync::Arc;
+
+use serde::Serialize;
+
+/// How far a transcription has got.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct Progress {
+    /// Audio transcribed so far, in milliseconds. When every channel is
+    /// transcribed separately this counts up across the channels.
+    pub processed_ms: i64,
+    /// Audio to transcribe in all, when the source knows its length: the
+    /// input's length, times the channel count when channels are separate.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub total_ms: Option<i64>,
+}
+
+/// A handle for cancelling a transcription and receiving its progress.
+///
+/// Clones share the same state, so one clone can be kept to call
+/// [`RunControl::cancel`] while another is passed to the transcriber.
+#[derive(Clone, Default)]
+pub struct RunControl {
+    inner: Arc<Inner>,
+}
+
+struct Inner {
+    cancelled: AtomicBool,
+    on_progress: Option<Box<dyn Fn(Progress) + Send + Sync>>,
+    /// Window being decoded and the input length, in milliseconds; -1 when unknown.
+    window_start_ms: AtomicI64,
+    window_ms: AtomicI64,
+    total_ms: AtomicI64,
+    /// Passes over the input (one per separately transcribed channel), and
+    /// the progress already made by the finished ones.
+    passes: AtomicI64,
+    pass_offset_ms: AtomicI64,
+    /// Furthest point reported, so progress never moves backwards.
+    reported_ms: AtomicI64,
+}
+
+impl Default for Inner {
+    fn default() -> Self {
+        Inner {
+            cancelled: AtomicBool::new(false),
+            on_progress: None,
+            window_start_ms: AtomicI64::new(0),
+            window_ms: AtomicI64::new(0),
+            total_ms: AtomicI64::new(-1),
+            passes: AtomicI64::new(1),
+            pass_offset_ms: AtomicI64::new(0),
+            reported_ms: AtomicI64::new(0),
+        }
+    }
+}
+
+impl fmt::Debug for RunControl {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RunControl").field("cancelled", &self.is_cancelled()).finish_non_exhaustive()
+    }
+}
+
+impl RunControl {
+    /// A control that calls `on_progress` as decoding advances.
+    pub fn with_progress(on_progress: impl Fn(Progress) + Send + Sync + 'static) -> Self {
+        Self { inner: Arc::new(Inner { on_progress: Some(Box::new(on_progress)), ..Inner::default() }) }
+    }
+
+    /// Asks the transcription to stop; it fails with [`crate::SttError::Cancelled`].
+    pub fn cancel(&self) {
+        self.inner.cancelled.store(true, Ordering::SeqCst);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.inner.cancelled.load(Ordering::SeqCst)
+    }
+
+    /// Whether anyone listens for progress.
+    pub(crate) fn wants_progress(&self) -> bool {
+        self.inner.on_progress.is_some()
+    }
+
+    pub(crate) fn set_total_ms(&self, total_ms: Option<i64>) {
+        self.inner.total_ms.store(total_ms.unwrap_or(-1), Ordering::Relaxed);
+    }
+
+    /// Length of the input, once a run has opened it and it is known.
+    pub(crate) fn total_ms(&self) -> Option<i64> {
+        let total = self.inner.total_ms.load(Ordering::Relaxed);
+        (total >= 0).then_some(total)
+    }
+
+    /// Starts another of `count` passes over the same input, so progress
+    /// keeps counting up from where the previous pass ended.
+    pub(crate) fn begin_pass(&self, count: usize) {
+        self.inner.passes.store(count.max(1) as i64, Ordering::Relaxed);
+        self.inner.pass_offset_ms.store(self.inner.reported_ms.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+
+    /// Marks the audio about to be decoded, so whisper.cpp's per-call
+    /// percentages can be placed on the input's timeline.
+    pub(crate) fn begin_window(&self, start_ms: i64, length_ms: i64) {
+        self.inner.window_start_ms.store(start_ms, Ordering::Relaxed);
+        self.inner.window_ms.store(length_ms, Ordering::Relaxed);
+    }
+
+    /// Reports `percent` of the current window as done.
+    pub(crate) fn report_percent(&self, percent: i32) {
+        let start = self.inner.window_start_ms.load(Ordering::Relaxed);
+        let length = self.inner.window_ms.load(Ordering::Relaxed);
+        self.report(start + length * percent.clamp(0, 100) as i64 / 100);
+    }
+
+    /// Reports the current pass as transcribed up to `processed_ms`.
+    pub(crate) fn report(&self, processed_ms: i64) {
+        let Some(on_progress) = &self.inner.on_progress else { return };
+        let processed_ms = self.inner.pass_offset_ms.load(Ordering::Relaxed) + processed_ms;
+        if self.inner.reported_ms.fetch_max(processed_ms, Ordering::Relaxed) >= processed_ms {
+            return;
+        }
+        let passes = self.inner.passes.load(Ordering::Relaxed);
+        on_progress(Progress { processed_ms, total_ms: self.total_ms().map(|total| total * passes) });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+
+    fn recording() -> (RunControl, Arc<Mutex<Vec<Progress>>>) {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let sink = seen.clone();
+        (RunControl::with_progress(move |progress| sink.lock().unwrap().push(progress)), seen)
+    }
+
+    #[test]
+    fn progress_only_moves_forward() {
+        let (control, seen) = recording();
+        control.set_total_ms(Some(10_000));
+        control.begin_window(0, 4_000);
+        control.report_percent(50);
+        control.report(1_000);
+        control.report(4_000);
+        let processed: Vec<i64> = seen.lock().unwrap().iter().map(|p| p.processed_ms).collect();
+        assert_eq!(processed, vec![2_000, 4_000]);
+        assert_eq!(seen.lock().unwrap()[0].total_ms, Some(10_000));
+    }
+
+    #[test]
+    fn progress_counts_up_across_passes() {
+        let (control, seen) = recording();
+        for _ in 0..2 {
+            control.begin_pass(2);
+            control.set_total_ms(Some(6_000));
+            control.report(3_000);
+            control.report(6_000);
+        }
+        let progress: Vec<(i64, Option<i64>)> = seen.lock().unwrap().iter().map(|p| (p.processed_ms, p.total_ms)).collect();
+        assert_eq!(progress, vec![(3_000, Some(12_000)), (6_000, Some(12_000)), (9_000, Some(12_000)), (12_000, Some(12_000))]);
+        // The input's own length is still what the source reported.
+        assert_eq!(control.total_ms(), Some(6_000));
+    }
+}
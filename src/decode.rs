@@ -0,0 +1,328 @@
+//! In-process decoding of compressed audio containers.
+//!
+//! Containers are identified from their leading bytes rather than the file
+//! extension, then demuxed and decoded with symphonia, or with `ogg` and
+//! `opus-decoder` for Ogg/Opus, which symphonia lacks. No external binaries
+//! are involved.
+
+use std::fs::File;
+use std::io::{self, BufReader, Read};
+use std::path::Path;
+
+use symphonia::core::audio::SampleBuffer;
+use symphonia::core::codecs::{DecoderOptions, CODEC_TYPE_NULL};
+use symphonia::core::errors::Error as SymphoniaError;
+use symphonia::core::formats::FormatOptions;
+use symphonia::core::io::MediaSourceStream;
+use symphonia::core::meta::MetadataOptions;
+use symphonia::core::probe::Hint;
+
+use crate::audio::DecodedAudio;
+use crate::error::{Result, SttError};
+
+/// Audio container families recognised by [`detect_container`].
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Container {
+    Wav,
+    Mp3,
+    /// Raw AAC in ADTS framing.
+    Aac,
+    Flac,
+    /// Ogg carrying Vorbis.
+    Ogg,
+    /// Ogg carrying Opus.
+    Opus,
+    /// ISO base media (MP4/M4A), usually carrying AAC or ALAC.
+    Mp4,
+    Unknown,
+}
+
+impl Container {
+    fn extension_hint(self) -> Option<&'static str> {
+        match self {
+            Container::Wav => Some("wav"),
+            Container::Mp3 => Some("mp3"),
+            Container::Aac => Some("aac"),
+            Container::Flac => Some("flac"),
+            Container::Ogg | Container::Opus => Some("ogg"),
+            Container::Mp4 => Some("m4a"),
+            Container::Unknown => None,
+        }
+    }
+}
+
+/// Identifies the container from the first bytes of a file.
+///
+/// 64 bytes are enough to see past an Ogg page header to the codec magic.
+pub fn detect_container(header: &[u8]) -> Container {
+    match header {
+        [b'R', b'I', b'F', b'F', _, _, _, _, b'W', b'A', b'V', b'E', ..]
+        | [b'R', b'F', b'6', b'4', _, _, _, _, b'W', b'A', b'V', b'E', ..] => Container::Wav,
+        [b'f', b'L', b'a', b'C', ..] => Container::Flac,
+        [b'O', b'g', b'g', b'S', ..] => {
+            if header.windows(8).any(|w| w == b"OpusHead") {
+                Container::Opus
+            } else {
+                Container::Ogg
+            }
+        }
+        [_, _, _, _, b'f', b't', b'y', b'p', ..] => Container::Mp4,
+        [b'I', b'D', b'3', ..] => Container::Mp3,
+        // ADTS sync word with layer bits 00.
+        [0xFF, b, ..] if b & 0xF6 == 0xF0 => Container::Aac,
+        // MPEG audio frame sync.
+        [0xFF, b, ..] if b & 0xE0 == 0xE0 => Container::Mp3,
+        _ => Container::Unknown,
+    }
+}
+
+/// Reads enough of `path` to run [`detect_container`].
+pub fn sniff_container(path: impl AsRef<Path>) -> io::Result<Container> {
+    let mut header = Vec::with_capacity(64);
+    File::open(path)?.take(64).read_to_end(&mut header)?;
+    Ok(detect_container(&header))
+}
+
+/// Decodes the first audio track of `path` to interleaved `f32` samples.
+pub fn decode_file(path: impl AsRef<Path>) -> Result<DecodedAudio> {
+    let path = path.as_ref();
+    let container = sniff_container(path)?;
+    if container == Container::Opus {
+        return decode_opus(path);
+    }
+
+    let mut hint = Hint::new();
+    if let Some(ext) = container.extension_hint() {
+        hint.with_extension(ext);
+    } else if let Some(ext) = path.extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+
+    let source = MediaSourceStream::new(Box::new(File::open(path)?), Default::default());
+    let probed = symphonia::default::get_probe()
+        .format(&hint, source, &FormatOptions::default(), &MetadataOptions::default())
+        .map_err(|e| SttError::UnsupportedFormat(format!("unrecognised audio format in '{}': {}", path.display(), e)))?;
+    let mut format = probed.format;
+
+    let track = format
+        .tracks()
+        .iter()
+        .find(|t| t.codec_params.codec != CODEC_TYPE_NULL)
+        .ok_or_else(|| SttError::decode(format!("'{}' contains no audio track", path.display())))?;
+    let track_id = track.id;
+    let mut sample_rate = track.codec_params.sample_rate.unwrap_or(0);
+    let mut channels = track.codec_params.channels.map(|c| c.count() as u16).unwrap_or(0);
+    let mut decoder = symphonia::default::get_codecs()
+        .make(&track.codec_params, &DecoderOptions::default())
+        .map_err(|e| SttError::UnsupportedFormat(format!("unsupported codec in '{}': {}", path.display(), e)))?;
+
+    let mut samples = Vec::new();
+    let mut buffer: Option<SampleBuffer<f32>> = None;
+    loop {
+        let packet = match format.next_packet() {
+            Ok(packet) => packet,
+            Err(SymphoniaError::IoError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => break,
+            Err(SymphoniaError::ResetRequired) => break,
+            Err(e) => return Err(e.into()),
+        };
+        if packet.track_id() != track_id {
+            continue;
+        }
+        let decoded = match decoder.decode(&packet) {
+            Ok(decoded) => decoded,
+            Err(SymphoniaError::DecodeError(e)) => {
+                eprintln!("Skipping undecodable packet in '{}': {}", path.display(), e);
+                continue;
+            }
+            Err(e) => return Err(e.into()),
+        };
+
+        let spec = *decoded.spec();
+        sample_rate = spec.rate;
+        channels = spec.channels.count() as u16;
+        let needed = decoded.capacity() as u64;
+        let buf = match &mut buffer {
+            Some(buf) if buf.capacity() as u64 >= needed * spec.channels.count() as u64 => buf,
+            _ => buffer.insert(SampleBuffer::new(needed, spec)),
+        };
+        buf.copy_interleaved_ref(decoded);
+        samples.extend_from_slice(buf.samples());
+    }
+
+    if sample_rate == 0 || channels == 0 {
+        return Err(SttError::decode(format!("'{}' does not declare a sample rate or channel layout", path.display())));
+    }
+    Ok(DecodedAudio { sample_rate, channels, samples })
+}
+
+/// Opus always decodes at 48 kHz, whatever rate the encoder was fed.
+const OPUS_RATE: u32 = 48_000;
+
+/// Largest Opus packet duration: 120 ms at 48 kHz.
+const OPUS_MAX_FRAME: usize = 5_760;
+
+/// The fields of an `OpusHead` identification header (RFC 7845, section 5.1).
+struct OpusHead {
+    channels: usize,
+    pre_skip: u64,
+    /// Output gain in Q7.8 dB.
+    gain: i16,
+    /// Streams, coupled streams and channel mapping of mapping families 1 and up.
+    multistream: Option<(usize, usize, Vec<u8>)>,
+}
+
+impl OpusHead {
+    fn parse(data: &[u8]) -> Option<Self> {
+        if data.len() < 19 || &data[..8] != b"OpusHead" {
+            return None;
+        }
+        let channels = data[9] as usize;
+        let multistream = match data[18] {
+            0 => None,
+            _ => {
+                let mapping = data.get(21..21 + channels)?.to_vec();
+                Some((data[19] as usize, data[20] as usize, mapping))
+            }
+        };
+        Some(OpusHead {
+            channels,
+            pre_skip: u16::from_le_bytes([data[10], data[11]]) as u64,
+            gain: i16::from_le_bytes([data[16], data[17]]),
+            multistream,
+        })
+    }
+}
+
+/// Either decoder, depending on the header's channel mapping family.
+enum OpusStreamDecoder {
+    Single(Box<opus_decoder::OpusDecoder>),
+    Multi(opus_decoder::OpusMultistreamDecoder),
+}
+
+impl OpusStreamDecoder {
+    fn decode(&mut self, packet: &[u8], pcm: &mut [f32]) -> std::result::Result<usize, opus_decoder::OpusError> {
+        match self {
+            OpusStreamDecoder::Single(decoder) => decoder.decode_float(packet, pcm, false),
+            OpusStreamDecoder::Multi(decoder) => decoder.decode_float(packet, pcm, false),
+        }
+    }
+}
+
+/// Decodes the first Opus stream of an Ogg file at 48 kHz.
+///
+/// The encoder's pre-skip is dropped from the start and the padding after the
+/// last page's granule position from the end, as RFC 7845 prescribes.
+fn decode_opus(path: &Path) -> Result<DecodedAudio> {
+    let mut reader = ogg::PacketReader::new(BufReader::new(File::open(path)?));
+    let invalid = |what: &str| SttError::decode(format!("'{}' {}", path.display(), what));
+
+    let next = |reader: &mut ogg::PacketReader<BufReader<File>>| match reader.read_packet() {
+        Ok(packet) => Ok(packet),
+        // A truncated file ends at the last complete page.
+        Err(ogg::OggReadError::ReadError(e)) if e.kind() == io::ErrorKind::UnexpectedEof => Ok(None),
+        Err(e) => Err(SttError::decode_with(format!("invalid Ogg data in '{}'", path.display()), e)),
+    };
+    let (serial, head) = loop {
+        let packet = next(&mut reader)?.ok_or_else(|| invalid("contains no Opus stream"))?;
+        if let Some(head) = OpusHead::parse(&packet.data) {
+            break (packet.stream_serial(), head);
+        }
+    };
+    if head.channels == 0 {
+        return Err(invalid("declares zero channels"));
+    }
+    let mut decoder = match &head.multistream {
+        None => opus_decoder::OpusDecoder::new(OPUS_RATE, head.channels).map(|d| OpusStreamDecoder::Single(Box::new(d))),
+        Some((streams, coupled, mapping)) => {
+            opus_decoder::OpusMultistreamDecoder::new(OPUS_RATE, head.channels, *streams, *coupled, mapping).map(OpusStreamDecoder::Multi)
+        }
+    }
+    .map_err(|e| SttError::UnsupportedFormat(format!("Opus stream in '{}': {}", path.display(), e)))?;
+
+    let mut samples = Vec::new();
+    let mut pcm = vec![0.0f32; OPUS_MAX_FRAME * head.channels];
+    let mut comments_seen = false;
+    let mut end_granule = None;
+    while let Some(packet) = next(&mut reader)? {
+        if packet.stream_serial() != serial {
+            continue;
+        }
+        // The comment header follows the identification header.
+        if !comments_seen {
+            comments_seen = true;
+            if packet.data.starts_with(b"OpusTags") {
+                continue;
+            }
+        }
+        match decoder.decode(&packet.data, &mut pcm) {
+            Ok(frames) => samples.extend_from_slice(&pcm[..frames * head.channels]),
+            Err(e) => eprintln!("Skipping undecodable packet in '{}': {}", path.display(), e),
+        }
+        if packet.last_in_page() {
+            end_granule = Some(packet.absgp_page());
+        }
+    }
+
+    let frames = (samples.len() / head.channels) as u64;
+    let keep = end_granule.map_or(frames, |granule| granule.min(frames)).saturating_sub(head.pre_skip.min(frames));
+    let start = head.pre_skip.min(frames) as usize * head.channels;
+    let mut samples = samples.split_off(start);
+    samples.truncate(keep as usize * head.channels);
+    if head.gain != 0 {
+        let scale = 10f32.powf(head.gain as f32 / (20.0 * 256.0));
+        samples.iter_mut().for_each(|s| *s *= scale);
+    }
+    Ok(DecodedAudio { sample_rate: OPUS_RATE, channels: head.channels as u16, samples })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn opus_head(channels: u8, pre_skip: u16) -> Vec<u8> {
+        let mut head = b"OpusHead".to_vec();
+        head.push(1);
+        head.push(channels);
+        head.extend_from_slice(&pre_skip.to_le_bytes());
+        head.extend_from_slice(&48_000u32.to_le_bytes());
+        head.extend_from_slice(&0i16.to_le_bytes());
+        head.push(0);
+        head
+    }
+
+    #[test]
+    fn detects_containers_from_magic_bytes() {
+        let ogg_page = |codec: &[u8]| [b"OggS".as_slice(), &[0; 24], codec].concat();
+        assert_eq!(detect_container(b"RIFF\0\0\0\0WAVEfmt "), Container::Wav);
+        assert_eq!(detect_container(b"fLaC\0\0\0\x22"), Container::Flac);
+        assert_eq!(detect_container(&ogg_page(b"\x01vorbis")), Container::Ogg);
+        assert_eq!(detect_container(&ogg_page(b"OpusHead")), Container::Opus);
+        assert_eq!(detect_container(b"\0\0\0\x20ftypM4A "), Container::Mp4);
+        assert_eq!(detect_container(b"ID3\x04\0\0"), Container::Mp3);
+        assert_eq!(detect_container(&[0xFF, 0xFB, 0x90, 0x64]), Container::Mp3);
+        assert_eq!(detect_container(&[0xFF, 0xF1, 0x50, 0x80]), Container::Aac);
+        assert_eq!(detect_container(b"plain text"), Container::Unknown);
+        assert_eq!(detect_container(b""), Container::Unknown);
+    }
+
+    #[test]
+    fn opus_output_drops_pre_skip_and_end_padding() {
+        let path = std::env::temp_dir().join(format!("ruststt-decode-{}.opus", std::process::id()));
+        let mut writer = ogg::PacketWriter::new(File::create(&path).unwrap());
+        writer.write_packet(opus_head(1, 312), 7, ogg::PacketWriteEndInfo::EndPage, 0).unwrap();
+        writer.write_packet(b"OpusTags\0\0\0\0\0\0\0\0".to_vec(), 7, ogg::PacketWriteEndInfo::EndPage, 0).unwrap();
+        // Ten 20 ms CELT frames with empty payloads, which decode to concealment.
+        for i in 1..=10u64 {
+            let end = if i == 10 { ogg::PacketWriteEndInfo::EndStream } else { ogg::PacketWriteEndInfo::NormalPacket };
+            // The last page declares 300 samples of encoder padding.
+            let granule = if i == 10 { 9_600 - 300 } else { i * 960 };
+            writer.write_packet(vec![0xF8], 7, end, granule).unwrap();
+        }
+        drop(writer);
+
+        let audio = decode_file(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!((audio.sample_rate, audio.channels), (48_000, 1));
+        assert_eq!(audio.samples.len(), 9_600 - 300 - 312);
+    }
+}
@@ -0,0 +1,161 @@
+//! Decoder search strategy and temperature fallback.
+//!
+//! Decoding starts at `temperature`. Whenever a segment's token entropy
+//! exceeds `entropy_threshold` (repetitive output) or its average
+//! log-probability falls below `logprob_threshold`, whisper.cpp retries it at
+//! a temperature `temperature_increment` higher, sampling `best_of`
+//! candidates, until it succeeds or reaches 1.0.
+//!
+//! The same settings can be kept in a TOML file:
+//!
+//! ```toml
+//! strategy = "greedy"
+//! best_of = 5
+//! temperature_increment = 0.2
+//! entropy_threshold = 2.4
+//! logprob_threshold = -1.0
+//! ```
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use whisper_rs::SamplingStrategy;
+
+use crate::error::{Result, SttError};
+
+/// Most candidates or beams whisper.cpp decodes at once (`WHISPER_MAX_DECODERS`).
+pub const MAX_DECODERS: i32 = 8;
+
+/// How candidate token sequences are searched.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum DecodingStrategy {
+    /// Take the most likely token at each step; fastest.
+    Greedy,
+    /// Keep the `beam_size` best partial sequences; more accurate, slower.
+    #[default]
+    #[serde(alias = "beam")]
+    BeamSearch,
+}
+
+impl std::str::FromStr for DecodingStrategy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "greedy" => Ok(DecodingStrategy::Greedy),
+            "beam" | "beam_search" | "beam-search" => Ok(DecodingStrategy::BeamSearch),
+            other => Err(format!("unknown decoding strategy '{}' (expected greedy or beam)", other)),
+        }
+    }
+}
+
+/// Search and fallback settings for the decoder.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct DecodingOptions {
+    pub strategy: DecodingStrategy,
+    /// Candidates sampled per fallback temperature when greedy.
+    pub best_of: i32,
+    /// Beam width when searching with beams.
+    pub beam_size: i32,
+    /// Beam search patience; whisper.cpp treats negative values as 1.0.
+    pub patience: f32,
+    /// Initial sampling temperature; 0 is deterministic.
+    pub temperature: f32,
+    /// Temperature step for each fallback retry; 0 disables fallback.
+    pub temperature_increment: f32,
+    /// Retry when the token entropy of a segment exceeds this.
+    pub entropy_threshold: f32,
+    /// Retry when the average token log-probability falls below this.
+    pub logprob_threshold: f32,
+}
+
+impl Default for DecodingOptions {
+    fn default() -> Self {
+        Self {
+            strategy: DecodingStrategy::BeamSearch,
+            best_of: 5,
+            beam_size: 2,
+            patience: -1.0,
+            temperature: 0.0,
+            temperature_increment: 0.2,
+            entropy_threshold: 2.4,
+            logprob_threshold: -1.0,
+        }
+    }
+}
+
+impl DecodingOptions {
+    /// Reads options from a TOML file; keys left out keep their defaults.
+    pub fn from_file(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)?;
+        let options: Self = toml::from_str(&text)
+            .map_err(|e| SttError::InvalidInput(format!("invalid decoding config '{}': {}", path.display(), e)))?;
+        options.validate()
+            .map_err(|e| SttError::InvalidInput(format!("invalid decoding config '{}': {}", path.display(), e)))?;
+        Ok(options)
+    }
+
+    /// Rejects candidate and beam counts whisper.cpp cannot run.
+    pub fn validate(&self) -> Result<()> {
+        for (name, value) in [("best_of", self.best_of), ("beam_size", self.beam_size)] {
+            if !(1..=MAX_DECODERS).contains(&value) {
+                return Err(SttError::InvalidInput(format!("{} must be between 1 and {}, not {}", name, MAX_DECODERS, value)));
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn sampling_strategy(&self) -> SamplingStrategy {
+        match self.strategy {
+            DecodingStrategy::Greedy => SamplingStrategy::Greedy { best_of: self.best_of },
+            DecodingStrategy::BeamSearch => SamplingStrategy::BeamSearch { beam_size: self.beam_size, patience: self.patience },
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_toml(name: &str, text: &str) -> Result<DecodingOptions> {
+        let path = std::env::temp_dir().join(format!("ruststt-decoding-{}-{}.toml", std::process::id(), name));
+        std::fs::write(&path, text).unwrap();
+        let options = DecodingOptions::from_file(&path);
+        std::fs::remove_file(&path).unwrap();
+        options
+    }
+
+    #[test]
+    fn toml_keys_override_defaults() {
+        let options = from_toml("partial", "strategy = \"greedy\"\nbest_of = 3\ntemperature_increment = 0.4\n").unwrap();
+        assert_eq!(
+            options,
+            DecodingOptions { strategy: DecodingStrategy::Greedy, best_of: 3, temperature_increment: 0.4, ..Default::default() }
+        );
+        assert_eq!(from_toml("alias", "strategy = \"beam\"\nbeam_size = 8\n").unwrap().beam_size, 8);
+        assert_eq!(from_toml("empty", "").unwrap(), DecodingOptions::default());
+    }
+
+    #[test]
+    fn toml_rejects_unknown_keys_and_bad_values() {
+        for (name, text) in [
+            ("unknown", "beam_width = 4\n"),
+            ("strategy", "strategy = \"sampling\"\n"),
+            ("type", "best_of = \"five\"\n"),
+            ("best-of", "best_of = 9\n"),
+            ("beam-size", "beam_size = 0\n"),
+        ] {
+            assert!(matches!(from_toml(name, text), Err(SttError::InvalidInput(_))), "{name}");
+        }
+    }
+
+    #[test]
+    fn validate_bounds_candidates_and_beams() {
+        assert!(DecodingOptions { best_of: 8, beam_size: 1, ..Default::default() }.validate().is_ok());
+        assert!(DecodingOptions { best_of: -1, ..Default::default() }.validate().is_err());
+        assert!(DecodingOptions { beam_size: MAX_DECODERS + 1, ..Default::default() }.validate().is_err());
+    }
+}
@@ -0,0 +1,490 @@
+//! Speaker labels for transcript segments.
+//!
+//! Segments are first grouped into speaker turns: with a tinydiarize model
+//! (`*-tdrz.bin`) whisper.cpp marks where the speaker changes, otherwise every
+//! segment is its own turn. Each turn is summarised by the mean and spread of
+//! its MFCCs, and turns are merged bottom-up by cosine distance until the
+//! requested number of speakers remains or no pair is similar enough.
+//!
+//! Features are computed while a chunk's audio is still in memory, so labels
+//! cost no extra pass over the input.
+
+use std::f32::consts::PI;
+
+use serde::Serialize;
+
+use crate::resample::WHISPER_SAMPLE_RATE;
+use crate::transcriber::Segment;
+
+/// Speaker diarization settings.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize)]
+pub struct DiarizeOptions {
+    pub enabled: bool,
+    /// Exact number of speakers, when known.
+    pub speakers: Option<usize>,
+    /// Without `speakers`, turns closer than this cosine distance (0 to 2)
+    /// are attributed to the same speaker.
+    pub threshold: f32,
+}
+
+impl Default for DiarizeOptions {
+    fn default() -> Self {
+        Self { enabled: false, speakers: None, threshold: 0.6 }
+    }
+}
+
+/// Label written for the speaker with zero-based index `index`.
+pub fn speaker_label(index: usize) -> String {
+    format!("SPEAKER_{:02}", index)
+}
+
+const FRAME: usize = 400;
+const HOP: usize = 160;
+const FFT_SIZE: usize = 512;
+const MEL_BANDS: usize = 26;
+const CEPSTRA: usize = 12;
+/// Frames quieter than this carry no voice information.
+const MIN_FRAME_DB: f32 = -50.0;
+/// Turns with fewer voiced frames than this (0.3 s) are labelled from their neighbours.
+const MIN_FRAMES: usize = 30;
+
+/// Summarises the voice in each segment's span of `samples`, which start
+/// `offset_ms` into the recording. `None` marks segments with too little voiced audio.
+pub fn segment_features(samples: &[f32], offset_ms: i64, segments: &[Segment]) -> Vec<Option<Vec<f32>>> {
+    let extractor = Mfcc::new();
+    segments
+        .iter()
+        .map(|segment| {
+            let to_index = |ms: i64| (((ms - offset_ms).max(0) as u64 * WHISPER_SAMPLE_RATE as u64 / 1000) as usize).min(samples.len());
+            let span = &samples[to_index(segment.start_ms)..to_index(segment.end_ms)];
+            let frames: Vec<[f32; CEPSTRA]> = span
+                .windows(FRAME)
+                .step_by(HOP)
+                .filter_map(|frame| extractor.cepstrum(frame))
+                .collect();
+            (frames.len() >= MIN_FRAMES).then(|| statistics(&frames))
+        })
+        .collect()
+}
+
+/// Mean and standard deviation of each cepstral coefficient.
+fn statistics(frames: &[[f32; CEPSTRA]]) -> Vec<f32> {
+    let n = frames.len() as f32;
+    let mut mean = [0.0f32; CEPSTRA];
+    for frame in frames {
+        for (m, c) in mean.iter_mut().zip(frame) {
+            *m += c / n;
+        }
+    }
+    let mut spread = [0.0f32; CEPSTRA];
+    for frame in frames {
+        for ((s, c), m) in spread.iter_mut().zip(frame).zip(&mean) {
+            *s += (c - m) * (c - m) / n;
+        }
+    }
+    mean.iter().copied().chain(spread.iter().map(|v| v.sqrt())).collect()
+}
+
+/// Sets `speaker` on every segment. `features` runs parallel to `segments`;
+/// `use_turns` groups segments by tinydiarize speaker-turn marks first.
+pub fn label_speakers(segments: &mut [Segment], features: &[Option<Vec<f32>>], use_turns: bool, options: &DiarizeOptions) {
+    if segments.is_empty() {
+        return;
+    }
+
+    // Consecutive segments of one speaker turn share a feature vector,
+    // the duration-weighted mean of theirs.
+    let mut turns: Vec<Turn> = Vec::new();
+    let mut open_new = true;
+    for (index, (segment, feature)) in segments.iter().zip(features).enumerate() {
+        if open_new || !use_turns {
+            turns.push(Turn { segments: Vec::new(), sum: None, weight: 0.0 });
+        }
+        let turn = turns.last_mut().expect("a turn was just opened");
+        turn.segments.push(index);
+        if let Some(feature) = feature {
+            let w = (segment.end_ms - segment.start_ms).max(1) as f32;
+            match &mut turn.sum {
+                Some(sum) => sum.iter_mut().zip(feature).for_each(|(s, f)| *s += f * w),
+                None => turn.sum = Some(feature.iter().map(|f| f * w).collect()),
+            }
+            turn.weight += w;
+        }
+        open_new = segment.speaker_turn;
+    }
+
+    let vectors: Vec<Option<Vec<f32>>> = turns
+        .iter()
+        .map(|t| t.sum.as_ref().map(|sum| sum.iter().map(|s| s / t.weight).collect()))
+        .collect();
+    let clusters = cluster(&standardize(&vectors), &turns, options);
+
+    // Featureless turns take the previous turn's speaker, or the next one's at the start.
+    let mut assigned: Vec<Option<usize>> = clusters;
+    for i in 1..assigned.len() {
+        if assigned[i].is_none() {
+            assigned[i] = assigned[i - 1];
+        }
+    }
+    for i in (0..assigned.len().saturating_sub(1)).rev() {
+        if assigned[i].is_none() {
+            assigned[i] = assigned[i + 1];
+        }
+    }
+
+    // Number speakers in order of first appearance.
+    let mut order: Vec<usize> = Vec::new();
+    for (turn, cluster) in turns.iter().zip(&assigned) {
+        let index = cluster.map(|c| match order.iter().position(|&o| o == c) {
+            Some(position) => position,
+            None => {
+                order.push(c);
+                order.len() - 1
+            }
+        }).unwrap_or(0);
+        for &s in &turn.segments {
+            segments[s].speaker = Some(speaker_label(index));
+        }
+    }
+}
+
+struct Turn {
+    segments: Vec<usize>,
+    sum: Option<Vec<f32>>,
+    weight: f32,
+}
+
+/// Z-scores every dimension across turns, so no coefficient dominates the distance.
+fn standardize(vectors: &[Option<Vec<f32>>]) -> Vec<Option<Vec<f32>>> {
+    let present: Vec<&Vec<f32>> = vectors.iter().flatten().collect();
+    let Some(dims) = present.first().map(|v| v.len()) else {
+        return vectors.to_vec();
+    };
+    let n = present.len() as f32;
+    let mean: Vec<f32> = (0..dims).map(|d| present.iter().map(|v| v[d]).sum::<f32>() / n).collect();
+    let std: Vec<f32> = (0..dims)
+        .map(|d| (present.iter().map(|v| (v[d] - mean[d]).powi(2)).sum::<f32>() / n).sqrt().max(1e-6))
+        .collect();
+    vectors
+        .iter()
+        .map(|v| v.as_ref().map(|v| v.iter().enumerate().map(|(d, x)| (x - mean[d]) / std[d]).collect()))
+        .collect()
+}
+
+fn cosine_distance(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = a.iter().map(|x| x * x).sum::<f32>().sqrt() * b.iter().map(|x| x * x).sum::<f32>().sqrt();
+    if norm <= f32::EPSILON { 1.0 } else { 1.0 - dot / norm }
+}
+
+/// Cosine distances between every pair of clusters, stored once per pair.
+struct Distances {
+    n: usize,
+    values: Vec<f32>,
+}
+
+impl Distances {
+    fn index(&self, a: usize, b: usize) -> usize {
+        let (i, j) = (a.min(b), a.max(b));
+        i * self.n - i * (i + 1) / 2 + (j - i - 1)
+    }
+
+    fn get(&self, a: usize, b: usize) -> f32 {
+        self.values[self.index(a, b)]
+    }
+
+    fn set(&mut self, a: usize, b: usize, distance: f32) {
+        let index = self.index(a, b);
+        self.values[index] = distance;
+    }
+}
+
+/// A group of turns: (member turns, weighted centroid sum, total weight).
+type Cluster = (Vec<usize>, Vec<f32>, f32);
+
+fn centroid(clusters: &[Option<Cluster>], i: usize) -> &[f32] {
+    clusters[i].as_ref().map_or(&[], |c| &c.1)
+}
+
+/// The closest live cluster to `i`, and its distance.
+fn nearest(i: usize, clusters: &[Option<Cluster>], distances: &Distances) -> Option<(usize, f32)> {
+    (0..clusters.len())
+        .filter(|&k| k != i && clusters[k].is_some())
+        .map(|k| (k, distances.get(i, k)))
+        .min_by(|a, b| a.1.total_cmp(&b.1))
+}
+
+/// Agglomerative clustering on duration-weighted centroids. Returns a
+/// cluster id per turn, `None` for turns without features.
+///
+/// Pairwise distances and each cluster's nearest neighbour are kept between
+/// merges, so a merge only recomputes distances to the merged cluster.
+fn cluster(vectors: &[Option<Vec<f32>>], turns: &[Turn], options: &DiarizeOptions) -> Vec<Option<usize>> {
+    let mut clusters: Vec<Option<Cluster>> = vectors
+        .iter()
+        .enumerate()
+        .filter_map(|(i, v)| v.as_ref().map(|v| Some((vec![i], v.iter().map(|x| x * turns[i].weight).collect(), turns[i].weight))))
+        .collect();
+    let n = clusters.len();
+    let target = options.speakers.unwrap_or(1).max(1);
+
+    let mut distances = Distances { n, values: vec![0.0; n * n.saturating_sub(1) / 2] };
+    for i in 0..n {
+        for j in i + 1..n {
+            distances.set(i, j, cosine_distance(centroid(&clusters, i), centroid(&clusters, j)));
+        }
+    }
+    let mut neighbours: Vec<Option<(usize, f32)>> = (0..n).map(|i| nearest(i, &clusters, &distances)).collect();
+
+    let mut live = n;
+    while live > target {
+        let best = neighbours
+            .iter()
+            .enumerate()
+            .filter_map(|(i, neighbour)| neighbour.map(|(j, d)| (i, j, d)))
+            .min_by(|a, b| a.2.total_cmp(&b.2));
+        let Some((a, b, distance)) = best else { break };
+        if options.speakers.is_none() && distance > options.threshold {
+            break;
+        }
+        let (i, j) = (a.min(b), a.max(b));
+        let (members, sum, weight) = clusters[j].take().expect("neighbours are live clusters");
+        let into = clusters[i].as_mut().expect("neighbours are live clusters");
+        into.0.extend(members);
+        into.1.iter_mut().zip(&sum).for_each(|(a, b)| *a += b);
+        into.2 += weight;
+        neighbours[j] = None;
+        live -= 1;
+
+        for k in (0..n).filter(|&k| k != i && clusters[k].is_some()) {
+            distances.set(i, k, cosine_distance(centroid(&clusters, i), centroid(&clusters, k)));
+        }
+        neighbours[i] = nearest(i, &clusters, &distances);
+        for k in (0..n).filter(|&k| k != i && clusters[k].is_some()) {
+            neighbours[k] = match neighbours[k] {
+                // The old neighbour is gone or moved away; look again.
+                Some((m, _)) if m == i || m == j => nearest(k, &clusters, &distances),
+                Some((_, d)) if distances.get(k, i) < d => Some((i, distances.get(k, i))),
+                other => other,
+            };
+        }
+    }
+
+    let mut assigned = vec![None; vectors.len()];
+    for (id, (members, _, _)) in clusters.iter().flatten().enumerate() {
+        for &m in members {
+            assigned[m] = Some(id);
+        }
+    }
+    assigned
+}
+
+/// Mel-frequency cepstral coefficients of 25 ms frames.
+struct Mfcc {
+    window: Vec<f32>,
+    /// `MEL_BANDS` triangular filters over the `FFT_SIZE / 2 + 1` power bins.
+    filters: Vec<Vec<(usize, f32)>>,
+}
+
+impl Mfcc {
+    fn new() -> Self {
+        let window = (0..FRAME).map(|n| 0.54 - 0.46 * (2.0 * PI * n as f32 / (FRAME - 1) as f32).cos()).collect();
+        let mel = |hz: f32| 2595.0 * (1.0 + hz / 700.0).log10();
+        let hz = |mel: f32| 700.0 * (10f32.powf(mel / 2595.0) - 1.0);
+        let (low, high) = (mel(20.0), mel(7600.0));
+        let bin = |f: f32| f * FFT_SIZE as f32 / WHISPER_SAMPLE_RATE as f32;
+        let edges: Vec<f32> = (0..MEL_BANDS + 2)
+            .map(|i| bin(hz(low + (high - low) * i as f32 / (MEL_BANDS + 1) as f32)))
+            .collect();
+        let filters = (0..MEL_BANDS)
+            .map(|b| {
+                let (left, centre, right) = (edges[b], edges[b + 1], edges[b + 2]);
+                (left.ceil() as usize..=right.floor() as usize)
+                    .map(|k| {
+                        let f = k as f32;
+                        let weight = if f <= centre { (f - left) / (centre - left) } else { (right - f) / (right - centre) };
+                        (k, weight.max(0.0))
+                    })
+                    .collect()
+            })
+            .collect();
+        Self { window, filters }
+    }
+
+    /// Cepstral coefficients 1..=12 of one frame, or `None` if it is near-silent.
+    fn cepstrum(&self, frame: &[f32]) -> Option<[f32; CEPSTRA]> {
+        let power = frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32;
+        if 10.0 * power.max(1e-12).log10() < MIN_FRAME_DB {
+            return None;
+        }
+
+        let mut re = [0.0f32; FFT_SIZE];
+        let mut im = [0.0f32; FFT_SIZE];
+        for (i, (s, w)) in frame.iter().zip(&self.window).enumerate() {
+            re[i] = s * w;
+        }
+        fft(&mut re, &mut im);
+
+        let log_mel: Vec<f32> = self.filters
+            .iter()
+            .map(|filter| {
+                let energy: f32 = filter.iter().map(|&(k, w)| w * (re[k] * re[k] + im[k] * im[k])).sum();
+                (energy + 1e-10).ln()
+            })
+            .collect();
+
+        let mut cepstra = [0.0f32; CEPSTRA];
+        for (c, out) in cepstra.iter_mut().enumerate() {
+            let k = (c + 1) as f32;
+            *out = log_mel
+                .iter()
+                .enumerate()
+                .map(|(m, e)| e * (PI * k * (m as f32 + 0.5) / MEL_BANDS as f32).cos())
+                .sum();
+        }
+        Some(cepstra)
+    }
+}
+
+/// In-place iterative radix-2 FFT.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let tr = re[b] * cos - im[b] * sin;
+                let ti = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - tr;
+                im[b] = im[a] - ti;
+                re[a] += tr;
+                im[a] += ti;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn turns(weights: &[f32]) -> Vec<Turn> {
+        weights.iter().map(|&weight| Turn { segments: Vec::new(), sum: None, weight }).collect()
+    }
+
+    /// Cluster ids renumbered by first appearance, so partitions compare equal.
+    fn canonical(ids: &[Option<usize>]) -> Vec<Option<usize>> {
+        let mut order = Vec::new();
+        ids.iter()
+            .map(|id| id.map(|id| order.iter().position(|&o| o == id).unwrap_or_else(|| { order.push(id); order.len() - 1 })))
+            .collect()
+    }
+
+    /// The straightforward all-pairs search per merge that `cluster` must agree with.
+    fn cluster_by_rescanning(vectors: &[Option<Vec<f32>>], turns: &[Turn], options: &DiarizeOptions) -> Vec<Option<usize>> {
+        let mut clusters: Vec<(Vec<usize>, Vec<f32>)> = vectors
+            .iter()
+            .enumerate()
+            .filter_map(|(i, v)| v.as_ref().map(|v| (vec![i], v.iter().map(|x| x * turns[i].weight).collect())))
+            .collect();
+        while clusters.len() > options.speakers.unwrap_or(1) {
+            let mut best: Option<(usize, usize, f32)> = None;
+            for i in 0..clusters.len() {
+                for j in i + 1..clusters.len() {
+                    let d = cosine_distance(&clusters[i].1, &clusters[j].1);
+                    if best.is_none_or(|(_, _, bd)| d < bd) {
+                        best = Some((i, j, d));
+                    }
+                }
+            }
+            let Some((i, j, d)) = best else { break };
+            if options.speakers.is_none() && d > options.threshold {
+                break;
+            }
+            let (members, sum) = clusters.remove(j);
+            clusters[i].0.extend(members);
+            clusters[i].1.iter_mut().zip(&sum).for_each(|(a, b)| *a += b);
+        }
+        let mut assigned = vec![None; vectors.len()];
+        for (id, (members, _)) in clusters.iter().enumerate() {
+            for &m in members {
+                assigned[m] = Some(id);
+            }
+        }
+        assigned
+    }
+
+    #[test]
+    fn cluster_groups_similar_turns_and_skips_featureless_ones() {
+        let vectors = vec![Some(vec![1.0, 0.0]), Some(vec![0.0, 1.0]), None, Some(vec![0.9, 0.1]), Some(vec![0.1, 0.9])];
+        let turns = turns(&[1.0; 5]);
+        let options = DiarizeOptions { enabled: true, ..Default::default() };
+        assert_eq!(canonical(&cluster(&vectors, &turns, &options)), vec![Some(0), Some(1), None, Some(0), Some(1)]);
+
+        let one = DiarizeOptions { speakers: Some(1), ..options };
+        assert_eq!(canonical(&cluster(&vectors, &turns, &one)), vec![Some(0), Some(0), None, Some(0), Some(0)]);
+    }
+
+    #[test]
+    fn cluster_matches_rescanning_every_pair() {
+        // A small linear congruential generator keeps the vectors reproducible.
+        let mut seed = 0x2545_f491u32;
+        let mut next = move || {
+            seed = seed.wrapping_mul(1_664_525).wrapping_add(1_013_904_223);
+            (seed >> 8) as f32 / (1 << 24) as f32 - 0.5
+        };
+        let vectors: Vec<Option<Vec<f32>>> = (0..60).map(|_| Some((0..6).map(|_| next()).collect())).collect();
+        let turns = turns(&(0..60).map(|i| 1.0 + (i % 7) as f32).collect::<Vec<_>>());
+        for options in [
+            DiarizeOptions { enabled: true, speakers: Some(4), threshold: 0.6 },
+            DiarizeOptions { enabled: true, speakers: None, threshold: 0.4 },
+        ] {
+            assert_eq!(
+                canonical(&cluster(&vectors, &turns, &options)),
+                canonical(&cluster_by_rescanning(&vectors, &turns, &options)),
+                "{options:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn label_speakers_follows_turns_and_first_appearance() {
+        let segment = |speaker_turn| Segment { start_ms: 0, end_ms: 1_000, speaker_turn, ..Default::default() };
+        let mut segments = vec![segment(false), segment(true), segment(false), segment(true), segment(false)];
+        let a = Some(vec![1.0, 0.0, 0.5]);
+        let b = Some(vec![0.0, 1.0, 0.5]);
+        // The third segment has no features of its own but shares the second turn.
+        let features = vec![a.clone(), a.clone(), None, b.clone(), a.clone()];
+        let options = DiarizeOptions { enabled: true, speakers: Some(2), threshold: 0.6 };
+        label_speakers(&mut segments, &features, true, &options);
+        let labels: Vec<&str> = segments.iter().map(|s| s.speaker.as_deref().unwrap()).collect();
+        assert_eq!(labels, vec!["SPEAKER_00", "SPEAKER_00", "SPEAKER_01", "SPEAKER_01", "SPEAKER_00"]);
+    }
+
+    #[test]
+    fn label_speakers_fills_featureless_segments_from_neighbours() {
+        let mut segments = vec![Segment::default(); 4];
+        let features = vec![None, Some(vec![1.0, 0.0]), None, Some(vec![0.0, 1.0])];
+        let options = DiarizeOptions { enabled: true, speakers: Some(2), threshold: 0.6 };
+        label_speakers(&mut segments, &features, false, &options);
+        let labels: Vec<&str> = segments.iter().map(|s| s.speaker.as_deref().unwrap()).collect();
+        assert_eq!(labels, vec!["SPEAKER_00", "SPEAKER_00", "SPEAKER_00", "SPEAKER_01"]);
+    }
+}
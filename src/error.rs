@@ -0,0 +1,109 @@
+//! The error type shared by every `ruststt` API.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+
+use whisper_rs::WhisperError;
+
+type BoxError = Box<dyn Error + Send + Sync + 'static>;
+
+/// Everything that can go wrong between reading an input and returning a transcript.
+#[derive(Debug)]
+pub enum SttError {
+    /// A file could not be opened, read or written.
+    Io(io::Error),
+    /// The input is a recognised format but its contents could not be decoded.
+    AudioDecode {
+        message: String,
+        source: Option<BoxError>,
+    },
+    /// The container or codec is not supported.
+    UnsupportedFormat(String),
+    /// ffmpeg could not repair a WAV file that failed to parse.
+    Repair(String),
+    /// The Whisper model could not be loaded.
+    ModelLoad {
+        path: PathBuf,
+        source: BoxError,
+    },
+    /// whisper.cpp failed while creating a state or running the decoder.
+    Inference(WhisperError),
+    /// An option does not fit the input, e.g. selecting a channel that does not exist.
+    InvalidInput(String),
+    /// The run was stopped through its [`crate::RunControl`].
+    Cancelled,
+}
+
+/// Shorthand for results carrying an [`SttError`].
+pub type Result<T, E = SttError> = std::result::Result<T, E>;
+
+impl SttError {
+    pub(crate) fn decode(message: impl Into<String>) -> Self {
+        SttError::AudioDecode { message: message.into(), source: None }
+    }
+
+    pub(crate) fn decode_with(message: impl Into<String>, source: impl Into<BoxError>) -> Self {
+        SttError::AudioDecode { message: message.into(), source: Some(source.into()) }
+    }
+}
+
+impl fmt::Display for SttError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SttError::Io(e) => write!(f, "I/O error: {}", e),
+            SttError::AudioDecode { message, source: Some(source) } => write!(f, "{}: {}", message, source),
+            SttError::AudioDecode { message, source: None } => write!(f, "{}", message),
+            SttError::UnsupportedFormat(message) => write!(f, "unsupported format: {}", message),
+            SttError::Repair(message) => write!(f, "repair failed: {}", message),
+            SttError::ModelLoad { path, source } => write!(f, "failed to load model '{}': {}", path.display(), source),
+            SttError::Inference(e) => write!(f, "inference failed: {}", e),
+            SttError::InvalidInput(message) => write!(f, "{}", message),
+            SttError::Cancelled => write!(f, "transcription cancelled"),
+        }
+    }
+}
+
+impl Error for SttError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SttError::Io(e) => Some(e),
+            SttError::AudioDecode { source, .. } => source.as_deref().map(|e| e as &(dyn Error + 'static)),
+            SttError::ModelLoad { source, .. } => Some(source.as_ref()),
+            SttError::Inference(e) => Some(e),
+            SttError::UnsupportedFormat(_) | SttError::Repair(_) | SttError::InvalidInput(_) | SttError::Cancelled => None,
+        }
+    }
+}
+
+impl From<io::Error> for SttError {
+    fn from(e: io::Error) -> Self {
+        SttError::Io(e)
+    }
+}
+
+impl From<hound::Error> for SttError {
+    fn from(e: hound::Error) -> Self {
+        match e {
+            hound::Error::Unsupported => SttError::UnsupportedFormat("WAV variant not supported by hound".to_string()),
+            e => SttError::decode_with("invalid WAV data", e),
+        }
+    }
+}
+
+impl From<symphonia::core::errors::Error> for SttError {
+    fn from(e: symphonia::core::errors::Error) -> Self {
+        use symphonia::core::errors::Error as SymphoniaError;
+        match e {
+            SymphoniaError::Unsupported(what) => SttError::UnsupportedFormat(what.to_string()),
+            e => SttError::decode_with("failed to decode audio", e),
+        }
+    }
+}
+
+impl From<WhisperError> for SttError {
+    fn from(e: WhisperError) -> Self {
+        SttError::Inference(e)
+    }
+}
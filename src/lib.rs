@@ -0,0 +1,45 @@
+//! Offline Whisper speech-to-text.
+//!
+//! ```no_run
+//! use ruststt::{TranscribeOptions, Transcriber};
+//!
+//! let transcriber = Transcriber::new("models/ggml-base.en.bin")?;
+//! let transcript = transcriber.transcribe_file("audio.wav", &TranscribeOptions::default())?;
+//! for segment in &transcript.segments {
+//!     println!("{} - {}: {}", segment.start_ms, segment.end_ms, segment.text);
+//! }
+//! # Ok::<(), ruststt::SttError>(())
+//! ```
+
+pub mod audio;
+pub mod batch;
+mod channels;
+pub mod chunk;
+mod control;
+pub mod decode;
+mod decoding;
+pub mod diarize;
+mod error;
+pub mod output;
+pub mod prompt;
+mod repair;
+pub mod resample;
+pub mod rpc;
+pub mod server;
+pub mod stream;
+mod transcriber;
+pub mod vad;
+mod words;
+
+pub use audio::{AudioOptions, ChannelMode, DecodedAudio};
+pub use chunk::ChunkOptions;
+pub use control::{Progress, RunControl};
+pub use decoding::{DecodingOptions, DecodingStrategy};
+pub use diarize::DiarizeOptions;
+pub use error::{Result, SttError};
+pub use output::{OutputFormat, RenderOptions, SubtitleOptions};
+pub use resample::{ResampleQuality, WHISPER_SAMPLE_RATE};
+pub use stream::{StreamEvent, StreamOptions, StreamTranscriber};
+pub use transcriber::{default_threads, DetectedLanguage, ModelOptions, RunInfo, Segment, Task, TranscribeOptions, Transcriber, Transcript};
+pub use vad::{SpeechDetector, VadMode, VadOptions};
+pub use words::{DtwPreset, Word, WordTiming};
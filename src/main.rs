@@ -1,112 +1,609 @@
-use hound;
-use whisper_rs::{WhisperContext, WhisperContextParameters, FullParams, SamplingStrategy};
-use std::process::Command;
-use std::path::Path;
-use std::error::Error;
+use clap::{Args, Parser, Subcommand};
+use ruststt::{batch, output, prompt, rpc, server, AudioOptions, ChannelMode, ChunkOptions, DecodingOptions, DecodingStrategy, DiarizeOptions, DtwPreset, ModelOptions, OutputFormat, RenderOptions, ResampleQuality, SttError, SubtitleOptions, Task, TranscribeOptions, Transcriber, VadMode, VadOptions};
+use ruststt::stream::{PcmConverter, PcmFormat, SampleEncoding, StreamEvent, StreamOptions, StreamTranscriber};
+use std::io::{self, Read, Write};
+use serde_json::json;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::fs;
-use std::time::Instant;
+use std::process::ExitCode;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
-fn fix_and_open_wav_inplace(path_str: &str) -> Result<hound::WavReader<std::io::BufReader<fs::File>>, Box<dyn Error>> {
-    println!("Attempting to repair '{}' in-place with ffmpeg...", path_str);
+/// Offline Whisper speech-to-text.
+#[derive(Parser, Debug)]
+#[command(name = "ruststt", version, about, args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
+struct Cli {
+    #[command(subcommand)]
+    command: Option<Command>,
 
-    let input_path = Path::new(path_str);
-    let temp_path = input_path.with_extension("repaired.tmp.wav");
+    /// Audio files to transcribe
+    #[arg(required_unless_present_any = ["stream", "stdio_rpc"], value_name = "INPUT")]
+    inputs: Vec<PathBuf>,
 
-    let output = Command::new("ffmpeg")
-        .arg("-i")
-        .arg(path_str)
-        .arg("-c:a")
-        .arg("copy")
-        .arg("-y")
-        .arg(&temp_path)
-        .output()?;
+    #[command(flatten)]
+    options: Options,
 
-    if !output.status.success() {
-        let _ = fs::remove_file(&temp_path);
-        let stderr = String::from_utf8_lossy(&output.stderr);
-        return Err(format!(
-            "ffmpeg failed to repair the file. Is ffmpeg installed and in your PATH?\nffmpeg stderr: {}", 
-            stderr
-        ).into());
-    }
+    /// Transcribe raw PCM read from stdin as it arrives, printing final lines
+    /// to stdout and partial hypotheses to stderr (JSON lines with --format json/jsonl)
+    #[arg(long, conflicts_with_all = ["inputs", "out_dir"])]
+    stream: bool,
 
-    fs::rename(&temp_path, path_str)?;
-    println!("Successfully repaired and replaced '{}'.", path_str);
+    /// Run as a long-lived worker answering newline-delimited JSON-RPC requests
+    /// (transcribe, cancel, loadModel, shutdown) on stdin; responses and progress
+    /// go to stdout, logs to stderr. --model is loaded by the first transcribe
+    /// unless loadModel picks another
+    #[arg(long, conflicts_with_all = ["inputs", "out_dir", "stream"])]
+    stdio_rpc: bool,
 
-    hound::WavReader::open(path_str).map_err(|e| {
-        format!("Failed to open the now-repaired file '{}': {}", path_str, e).into()
-    })
+    /// Transcriptions --stdio-rpc runs at once, each on its own decoder state;
+    /// further requests wait (default: one per four physical cores)
+    #[arg(long, requires = "stdio_rpc", conflicts_with_all = ["inputs", "stream"], value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    rpc_jobs: Option<usize>,
+
+    /// Sample rate of the PCM on stdin, 8000 to 192000 (--stream)
+    #[arg(long, default_value_t = 16_000, value_parser = clap::value_parser!(u32).range(8_000..=192_000))]
+    stream_rate: u32,
+
+    /// Interleaved channels of the PCM on stdin, 1 to 32 (--stream)
+    #[arg(long, default_value_t = 1, value_parser = clap::value_parser!(u16).range(1..=32))]
+    stream_channels: u16,
+
+    /// Sample encoding of the PCM on stdin [s16le, f32le] (--stream)
+    #[arg(long, default_value = "s16le")]
+    stream_format: SampleEncoding,
+
+    /// Decode the rolling window again after this much new audio, in milliseconds (--stream)
+    #[arg(long, default_value_t = 2000)]
+    step_ms: u32,
+
+    /// Longest window decoded at once before pending text is forced final, in milliseconds (--stream)
+    #[arg(long, default_value_t = 15_000)]
+    window_ms: u32,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Transcribe every audio file in directories or glob patterns with one loaded model
+    Batch {
+        /// Directories (their audio files), quoted glob patterns such as
+        /// 'calls/**/*.wav', or individual files
+        #[arg(required = true, value_name = "DIR|GLOB")]
+        sources: Vec<String>,
+
+        /// Files transcribed concurrently, each on its own decoder state
+        /// (default: one per four physical cores)
+        #[arg(short, long, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        jobs: Option<usize>,
+
+        /// Also write the summary of successes and failures as JSON to this file
+        #[arg(long, value_name = "FILE")]
+        report: Option<PathBuf>,
+
+        #[command(flatten)]
+        options: Options,
+    },
+    /// Serve OpenAI-compatible /v1/audio/transcriptions and /v1/audio/translations over HTTP,
+    /// and live transcription over a WebSocket on /v1/audio/stream
+    Serve {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080", value_name = "ADDR")]
+        listen: String,
+
+        /// Requests transcribed concurrently, each on its own decoder state
+        #[arg(long, default_value_t = 1, value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+        workers: usize,
+
+        /// Largest accepted upload, in megabytes
+        #[arg(long, default_value_t = 25, value_name = "MB")]
+        max_upload_mb: usize,
+
+        /// WebSocket stream sessions allowed at once, each on its own decoder state
+        #[arg(long, default_value_t = 4)]
+        max_streams: usize,
+
+        #[command(flatten)]
+        options: Options,
+    },
 }
 
-fn main() -> Result<(), Box<dyn Error>> {
+/// Model, decoding and output options shared by every mode.
+#[derive(Args, Debug)]
+struct Options {
+    /// Path to the ggml Whisper model
+    #[arg(short, long, default_value = "models/ggml-base.en.bin")]
+    model: PathBuf,
+
+    /// Spoken language of the input audio, or `auto` to detect it (needs a multilingual model)
+    #[arg(short, long, default_value = "en")]
+    language: String,
+
+    /// Transcribe in the spoken language or translate into English [transcribe, translate]
+    #[arg(long, default_value = "transcribe")]
+    task: Task,
+
+    /// TOML file with decoding settings (strategy, best_of, beam_size,
+    /// patience, temperature, ...); command-line flags take precedence
+    #[arg(long, value_name = "FILE")]
+    decoding_config: Option<PathBuf>,
+
+    /// Decoder search strategy [greedy, beam] (default: beam)
+    #[arg(long)]
+    strategy: Option<DecodingStrategy>,
+
+    /// Candidates sampled per fallback temperature with greedy decoding, 1 to 8 (default: 5)
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..=8))]
+    best_of: Option<i32>,
+
+    /// Beam width used by the beam-search decoder, 1 to 8 (default: 2)
+    #[arg(long, value_parser = clap::value_parser!(i32).range(1..=8))]
+    beam_size: Option<i32>,
+
+    /// Beam search patience (default: whisper.cpp's 1.0)
+    #[arg(long)]
+    patience: Option<f32>,
+
+    /// Initial sampling temperature (default: 0)
+    #[arg(long)]
+    temperature: Option<f32>,
+
+    /// Temperature added on each fallback retry; 0 disables fallback (default: 0.2)
+    #[arg(long)]
+    temperature_increment: Option<f32>,
+
+    /// Retry a segment whose token entropy exceeds this (default: 2.4)
+    #[arg(long, allow_hyphen_values = true)]
+    entropy_threshold: Option<f32>,
+
+    /// Retry a segment whose average token log-probability is below this (default: -1.0)
+    #[arg(long, allow_hyphen_values = true)]
+    logprob_threshold: Option<f32>,
+
+    /// Inference threads per transcription (default: one per physical core;
+    /// in `batch`, the physical cores split evenly between jobs)
+    #[arg(short, long, value_parser = clap::value_parser!(i32).range(1..))]
+    threads: Option<i32>,
+
+    /// Attach word-level timing and confidence to every segment
+    #[arg(long)]
+    word_timestamps: bool,
+
+    /// Align word timestamps with DTW using this model's alignment heads
+    /// (`auto` infers them from the model file name); implies --word-timestamps
+    #[arg(long, value_name = "PRESET")]
+    dtw: Option<DtwPreset>,
+
+    /// Text to condition the decoder on, e.g. the topic or names in the call
+    #[arg(long, value_name = "TEXT")]
+    prompt: Option<String>,
+
+    /// File of names and jargon, one per line, to bias spelling towards
+    #[arg(long, value_name = "FILE")]
+    vocabulary: Option<PathBuf>,
+
+    /// Condition each chunk, speech region or stream step on the text transcribed before it
+    #[arg(long)]
+    carry_context: bool,
+
+    /// Label segments with speakers (SPEAKER_00, SPEAKER_01, ...); uses the
+    /// speaker turns of tinydiarize (`*-tdrz.bin`) models when loaded
+    #[arg(long)]
+    diarize: bool,
+
+    /// Exact number of speakers for --diarize (default: estimated)
+    #[arg(long, value_name = "N", value_parser = clap::value_parser!(u32).range(1..), requires = "diarize")]
+    speakers: Option<u32>,
+
+    /// Voice distance below which --diarize attributes turns to one speaker,
+    /// from 0 to 2; lower finds more speakers
+    #[arg(long, default_value_t = 0.6, requires = "diarize")]
+    speaker_threshold: f32,
+
+    /// Resampling filter quality for non-16 kHz input [fast, medium, high]
+    #[arg(long, default_value = "medium")]
+    resample_quality: ResampleQuality,
+
+    /// Multi-channel handling: `downmix`, a zero-based channel index, or
+    /// `separate` to transcribe each channel on its own and merge them
+    #[arg(long, default_value = "downmix")]
+    channel: ChannelMode,
+
+    /// Comma-separated names for the channels of --channel separate,
+    /// e.g. `agent,customer` (default: CHANNEL_0, CHANNEL_1, ...)
+    #[arg(long, value_name = "NAMES", value_delimiter = ',')]
+    channel_labels: Vec<String>,
+
+    /// Overwrite an unreadable input with its ffmpeg-repaired copy instead of
+    /// repairing into a temporary file
+    #[arg(long)]
+    repair_in_place: bool,
+
+    /// Skip silence before inference [off, energy, silero]
+    #[arg(long, default_value = "off")]
+    vad: VadMode,
+
+    /// ggml Silero VAD model used by --vad silero
+    #[arg(long, value_name = "PATH")]
+    vad_model: Option<PathBuf>,
+
+    /// Pauses shorter than this stay inside one speech region, in milliseconds
+    #[arg(long, default_value_t = 500)]
+    vad_min_silence_ms: u32,
+
+    /// Audio kept around each speech region, in milliseconds
+    #[arg(long, default_value_t = 200)]
+    vad_pad_ms: u32,
+
+    /// Audio decoded per inference call, in milliseconds; long files are
+    /// read and transcribed one chunk at a time
+    #[arg(long, default_value_t = 30_000)]
+    chunk_ms: u32,
+
+    /// Audio shared by consecutive chunks, in milliseconds
+    #[arg(long, default_value_t = 2_000)]
+    chunk_overlap_ms: u32,
+
+    /// Format of the written transcript [text, plain, srt, vtt, json, jsonl]
+    #[arg(long, visible_alias = "format", default_value = "text")]
+    output_format: OutputFormat,
+
+    /// Maximum characters per subtitle line (srt, vtt)
+    #[arg(long, default_value_t = 42)]
+    max_line_chars: usize,
+
+    /// Maximum lines per subtitle cue (srt, vtt)
+    #[arg(long, default_value_t = 2)]
+    max_lines: usize,
+
+    /// Maximum time a subtitle cue stays on screen, in milliseconds (srt, vtt)
+    #[arg(long, default_value_t = 7000)]
+    max_cue_ms: i64,
+
+    /// Write one transcript per input into this directory (default: stdout,
+    /// or next to each input for `batch`)
+    #[arg(long, value_name = "DIR")]
+    out_dir: Option<PathBuf>,
+}
+
+/// Exit status when some, but not necessarily all, batch inputs failed.
+const BATCH_FAILED: u8 = 9;
+
+/// Process exit status for each error kind, so callers can tell bad input
+/// apart from a broken installation.
+fn exit_code(error: &SttError) -> u8 {
+    match error {
+        SttError::InvalidInput(_) => 2,
+        SttError::Io(_) => 3,
+        SttError::AudioDecode { .. } => 4,
+        SttError::UnsupportedFormat(_) => 5,
+        SttError::Repair(_) => 6,
+        SttError::ModelLoad { .. } => 7,
+        SttError::Inference(_) | SttError::Cancelled => 8,
+    }
+}
+
+fn main() -> ExitCode {
     // 🔇 Install logging hooks to silence whisper.cpp/ggml debug output
     whisper_rs::install_logging_hooks();
 
-    let input_filename = "audio.wav";
-    let mut reader = fix_and_open_wav_inplace(input_filename)?;
-    
-    let spec = reader.spec();
-    println!("Sample rate: {}, Channels: {}, Bits per sample: {}", 
-             spec.sample_rate, spec.channels, spec.bits_per_sample);
-    
-    if spec.sample_rate != 16000 {
-        eprintln!("Warning: Whisper works best with 16kHz audio. Current: {}Hz", spec.sample_rate);
-    }
-    
-    let audio_data: Vec<f32> = match spec.bits_per_sample {
-        16 => {
-            if spec.channels == 2 {
-                let samples = reader.samples::<i16>().collect::<Result<Vec<_>, _>>()?;
-                samples.chunks_exact(2).map(|chunk| {
-                    let left = chunk[0] as f32 / 32768.0;
-                    let right = chunk[1] as f32 / 32768.0;
-                    (left + right) / 2.0
-                }).collect()
-            } else {
-                reader.samples::<i16>()
-                    .map(|s| s.map(|sample| sample as f32 / 32768.0))
-                    .collect::<Result<Vec<f32>, _>>()?
+    let cli = Cli::parse();
+    match run(&cli) {
+        Ok(code) => code,
+        Err(e) => {
+            eprintln!("error: {}", e);
+            ExitCode::from(exit_code(&e))
+        }
+    }
+}
+
+fn run(cli: &Cli) -> Result<ExitCode, SttError> {
+    match &cli.command {
+        Some(Command::Batch { sources, jobs, report, options }) => return run_batch(sources, *jobs, report.as_deref(), options),
+        Some(Command::Serve { listen, workers, max_upload_mb, max_streams, options }) => {
+            let server_options = server::ServerOptions {
+                address: listen.clone(),
+                workers: *workers,
+                max_upload_bytes: max_upload_mb * 1024 * 1024,
+                max_streams: *max_streams,
+                ..Default::default()
+            };
+            run_serve(&server_options, options)?;
+            return Ok(ExitCode::SUCCESS);
+        }
+        None => {}
+    }
+
+    let opts = &cli.options;
+    let options = transcribe_options(opts)?;
+    if cli.stdio_rpc {
+        let rpc_options = rpc::RpcOptions {
+            model: opts.model.clone(),
+            model_options: ModelOptions { dtw: opts.dtw },
+            jobs: cli.rpc_jobs.unwrap_or_else(batch::default_jobs),
+        };
+        rpc::serve(&options, &render_options(opts), &rpc_options)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+    let out_paths = match &opts.out_dir {
+        Some(dir) => output_paths(&cli.inputs, Some(dir), opts.output_format)?,
+        None => HashMap::new(),
+    };
+    let transcriber = Transcriber::with_options(&opts.model, &ModelOptions { dtw: opts.dtw })?;
+    transcriber.check_options(&options)?;
+
+    if cli.stream {
+        run_stream(cli, &transcriber, options)?;
+        return Ok(ExitCode::SUCCESS);
+    }
+
+    if let Some(dir) = &opts.out_dir {
+        fs::create_dir_all(dir)?;
+    }
+
+    let render_options = render_options(opts);
+    for input in &cli.inputs {
+        let start = Instant::now();
+        let transcript = transcriber.transcribe_file(input, &options)?;
+        let duration = start.elapsed();
+        eprintln!("Transcription of '{}' completed in {:.2?}", input.display(), duration);
+
+        let rendered = output::render(&transcript, opts.output_format, &render_options);
+        match out_paths.get(input) {
+            Some(out_path) => {
+                fs::write(out_path, rendered)?;
+                eprintln!("Wrote '{}'", out_path.display());
             }
+            None => print!("{}", rendered),
+        }
+    }
+
+    Ok(ExitCode::SUCCESS)
+}
+
+/// Decoding settings from `--decoding-config`, overridden by individual flags.
+fn decoding_options(opts: &Options) -> Result<DecodingOptions, SttError> {
+    let mut decoding = match &opts.decoding_config {
+        Some(path) => DecodingOptions::from_file(path)?,
+        None => DecodingOptions::default(),
+    };
+    if let Some(strategy) = opts.strategy {
+        decoding.strategy = strategy;
+    }
+    if let Some(best_of) = opts.best_of {
+        decoding.best_of = best_of;
+    }
+    if let Some(beam_size) = opts.beam_size {
+        decoding.beam_size = beam_size;
+    }
+    if let Some(patience) = opts.patience {
+        decoding.patience = patience;
+    }
+    if let Some(temperature) = opts.temperature {
+        decoding.temperature = temperature;
+    }
+    if let Some(increment) = opts.temperature_increment {
+        decoding.temperature_increment = increment;
+    }
+    if let Some(threshold) = opts.entropy_threshold {
+        decoding.entropy_threshold = threshold;
+    }
+    if let Some(threshold) = opts.logprob_threshold {
+        decoding.logprob_threshold = threshold;
+    }
+    Ok(decoding)
+}
+
+fn transcribe_options(opts: &Options) -> Result<TranscribeOptions, SttError> {
+    Ok(TranscribeOptions {
+        language: opts.language.clone(),
+        task: opts.task,
+        decoding: decoding_options(opts)?,
+        threads: opts.threads,
+        word_timestamps: opts.word_timestamps || opts.dtw.is_some(),
+        audio: AudioOptions {
+            channels: opts.channel,
+            resample_quality: opts.resample_quality,
+            repair_in_place: opts.repair_in_place,
         },
-        32 => {
-            if spec.channels == 2 {
-                let samples = reader.samples::<f32>().collect::<Result<Vec<_>, _>>()?;
-                samples.chunks_exact(2).map(|chunk| (chunk[0] + chunk[1]) / 2.0).collect()
-            } else {
-                reader.samples::<f32>().collect::<Result<Vec<f32>, _>>()?
-            }
+        vad: VadOptions {
+            mode: opts.vad,
+            model: opts.vad_model.clone(),
+            min_silence_ms: opts.vad_min_silence_ms,
+            pad_ms: opts.vad_pad_ms,
+            ..VadOptions::default()
         },
-        _ => return Err(format!("Unsupported bit depth: {}", spec.bits_per_sample).into()),
-    };
-    
-    println!("Loaded {} audio samples", audio_data.len());
-    
-    let model_path = "models/ggml-base.en.bin";
-    let ctx = WhisperContext::new_with_params(model_path, WhisperContextParameters::default())
-        .expect("failed to load model");
-    
-    let mut params = FullParams::new(SamplingStrategy::BeamSearch { beam_size: 2, patience: -1.0 });
-    params.set_language(Some("en"));
-    params.set_print_progress(false);
-    params.set_print_realtime(false);
-    params.set_print_timestamps(false);
-    params.set_print_special(false);
-    
-    let mut state = ctx.create_state().expect("failed to create state");
+        chunking: ChunkOptions { chunk_ms: opts.chunk_ms, overlap_ms: opts.chunk_overlap_ms },
+        initial_prompt: opts.prompt.clone(),
+        vocabulary: match &opts.vocabulary {
+            Some(path) => prompt::load_vocabulary(path)?,
+            None => Vec::new(),
+        },
+        carry_context: opts.carry_context,
+        diarize: DiarizeOptions {
+            enabled: opts.diarize,
+            speakers: opts.speakers.map(|n| n as usize),
+            threshold: opts.speaker_threshold,
+        },
+        channel_labels: opts.channel_labels.clone(),
+    })
+}
+
+fn render_options(opts: &Options) -> RenderOptions {
+    RenderOptions {
+        subtitles: SubtitleOptions {
+            max_line_chars: opts.max_line_chars,
+            max_lines: opts.max_lines,
+            max_cue_ms: opts.max_cue_ms,
+        },
+    }
+}
 
+/// `<dir>/<input stem>.<format extension>`
+fn output_path(input: &Path, dir: &Path, format: OutputFormat) -> Result<PathBuf, SttError> {
+    let stem = input.file_stem().ok_or_else(|| {
+        SttError::InvalidInput(format!("'{}' has no file name", input.display()))
+    })?;
+    Ok(dir.join(format!("{}.{}", stem.to_string_lossy(), format.extension())))
+}
+
+/// The transcript path of every input: in `out_dir`, or next to the input.
+///
+/// Fails before anything is transcribed when two inputs, e.g. `call.wav` and
+/// `call.mp3`, would overwrite each other's transcript.
+fn output_paths(inputs: &[PathBuf], out_dir: Option<&Path>, format: OutputFormat) -> Result<HashMap<PathBuf, PathBuf>, SttError> {
+    let mut owners: HashMap<PathBuf, &Path> = HashMap::new();
+    let mut paths = HashMap::with_capacity(inputs.len());
+    for input in inputs {
+        let dir = out_dir.or_else(|| input.parent()).unwrap_or(Path::new("."));
+        let out_path = output_path(input, dir, format)?;
+        if let Some(other) = owners.insert(out_path.clone(), input) {
+            return Err(SttError::InvalidInput(format!(
+                "'{}' and '{}' would both be written to '{}'; rename one or transcribe them into separate --out-dir directories",
+                other.display(), input.display(), out_path.display()
+            )));
+        }
+        paths.insert(input.clone(), out_path);
+    }
+    Ok(paths)
+}
+
+fn run_batch(sources: &[String], jobs: Option<usize>, report: Option<&Path>, opts: &Options) -> Result<ExitCode, SttError> {
+    let mut inputs = Vec::new();
+    for source in sources {
+        inputs.extend(batch::collect_inputs(source)?);
+    }
+    inputs.sort();
+    inputs.dedup();
+    let out_paths = output_paths(&inputs, opts.out_dir.as_deref(), opts.output_format)?;
+
+    let options = transcribe_options(opts)?;
+    let transcriber = Transcriber::with_options(&opts.model, &ModelOptions { dtw: opts.dtw })?;
+    transcriber.check_options(&options)?;
+    let render_options = render_options(opts);
+    if let Some(dir) = &opts.out_dir {
+        fs::create_dir_all(dir)?;
+    }
+
+    let jobs = jobs.unwrap_or_else(batch::default_jobs).min(inputs.len());
+    let threads = options.threads.unwrap_or_else(|| batch::split_threads(jobs));
+    eprintln!("Transcribing {} file(s) with {} concurrent job(s) of {} thread(s)", inputs.len(), jobs, threads);
     let start = Instant::now();
-    state.full(params, &audio_data).expect("failed to run model");
-    let duration = start.elapsed();
-    println!("Transcription completed in {:.2?}", duration);
-    
-    println!("\nTranscription results:");
-    for segment in state.as_iter() {
-        println!("[{:.2}s - {:.2}s]: {}",
-            segment.start_timestamp() as f64 / 1000.0,
-            segment.end_timestamp() as f64 / 1000.0,
-            segment
-        );
-    }
-    
+    let outcomes = Mutex::new(Vec::with_capacity(inputs.len()));
+    batch::transcribe_batch(&transcriber, &inputs, &options, jobs, |item| {
+        let written = item.transcript.and_then(|transcript| {
+            let out_path = &out_paths[item.input];
+            fs::write(out_path, output::render(&transcript, opts.output_format, &render_options))?;
+            Ok(out_path.clone())
+        });
+        match &written {
+            Ok(out_path) => eprintln!("[ok] '{}' -> '{}' ({:.2?})", item.input.display(), out_path.display(), item.elapsed),
+            Err(e) => eprintln!("[failed] '{}': {}", item.input.display(), e),
+        }
+        outcomes.lock().unwrap().push((item.input.to_path_buf(), written, item.elapsed));
+    })?;
+
+    let mut outcomes = outcomes.into_inner().unwrap();
+    outcomes.sort_by(|a, b| a.0.cmp(&b.0));
+    let failed = outcomes.iter().filter(|(_, written, _)| written.is_err()).count();
+    eprintln!("Batch finished in {:.2?}: {} succeeded, {} failed", start.elapsed(), outcomes.len() - failed, failed);
+    for (input, written, _) in &outcomes {
+        if let Err(e) = written {
+            eprintln!("  {}: {}", input.display(), e);
+        }
+    }
+
+    if let Some(report) = report {
+        let entry = |(input, written, elapsed): &(PathBuf, Result<PathBuf, SttError>, Duration)| match written {
+            Ok(out_path) => json!({
+                "input": input, "output": out_path, "elapsed_ms": elapsed.as_millis() as u64,
+            }),
+            Err(e) => json!({
+                "input": input, "error": e.to_string(), "exit_code": exit_code(e), "elapsed_ms": elapsed.as_millis() as u64,
+            }),
+        };
+        let document = json!({
+            "total": outcomes.len(),
+            "succeeded": outcomes.iter().filter(|o| o.1.is_ok()).map(entry).collect::<Vec<_>>(),
+            "failed": outcomes.iter().filter(|o| o.1.is_err()).map(entry).collect::<Vec<_>>(),
+            "elapsed_ms": start.elapsed().as_millis() as u64,
+        });
+        let text = serde_json::to_string_pretty(&document).expect("batch report serializes to JSON");
+        fs::write(report, text + "\n")?;
+        eprintln!("Wrote '{}'", report.display());
+    }
+
+    Ok(if failed == 0 { ExitCode::SUCCESS } else { ExitCode::from(BATCH_FAILED) })
+}
+
+fn run_serve(server_options: &server::ServerOptions, opts: &Options) -> Result<(), SttError> {
+    let options = transcribe_options(opts)?;
+    let transcriber = Transcriber::with_options(&opts.model, &ModelOptions { dtw: opts.dtw })?;
+    transcriber.check_options(&options)?;
+    server::serve(&transcriber, &options, &render_options(opts), server_options)
+}
+
+fn run_stream(cli: &Cli, transcriber: &Transcriber, options: TranscribeOptions) -> Result<(), SttError> {
+    if options.diarize.enabled {
+        eprintln!("warning: --diarize is not applied to --stream");
+    }
+    let format = PcmFormat { sample_rate: cli.stream_rate, channels: cli.stream_channels, encoding: cli.stream_format };
+    let mut converter = PcmConverter::new(format, options.audio.channels, options.audio.resample_quality)?;
+    let stream_options = StreamOptions { step_ms: cli.step_ms, window_ms: cli.window_ms, ..StreamOptions::default() };
+    let mut session = StreamTranscriber::new(transcriber, options, stream_options)?;
+    let json = matches!(cli.options.output_format, OutputFormat::Json | OutputFormat::Jsonl);
+
+    let mut stdin = io::stdin().lock();
+    let mut buffer = vec![0u8; 64 * 1024];
+    loop {
+        let n = match stdin.read(&mut buffer) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+            Err(e) => return Err(e.into()),
+        };
+        let samples = converter.push(&buffer[..n]);
+        emit_stream_events(&session.push(&samples)?, json)?;
+    }
+    let tail = converter.flush();
+    emit_stream_events(&session.push(&tail)?, json)?;
+    emit_stream_events(&session.finish()?, json)
+}
+
+fn emit_stream_events(events: &[StreamEvent], json: bool) -> Result<(), SttError> {
+    let mut stdout = io::stdout().lock();
+    for event in events {
+        match (event, json) {
+            (event, true) => {
+                let line = serde_json::to_string(event).expect("stream events serialize to JSON");
+                writeln!(stdout, "{}", line)?;
+            }
+            (StreamEvent::Final(segment), false) => writeln!(stdout, "{}", segment.text)?,
+            (StreamEvent::Partial(segment), false) => eprintln!("... {}", segment.text),
+        }
+    }
+    stdout.flush()?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_paths_keep_shared_stems_in_their_own_directories() {
+        let inputs = vec![PathBuf::from("monday/call.wav"), PathBuf::from("tuesday/call.wav")];
+        let paths = output_paths(&inputs, None, OutputFormat::Srt).unwrap();
+        assert_eq!(paths[&inputs[0]], PathBuf::from("monday/call.srt"));
+        assert_eq!(paths[&inputs[1]], PathBuf::from("tuesday/call.srt"));
+    }
+
+    #[test]
+    fn output_paths_reject_inputs_that_would_overwrite_each_other() {
+        let across_dirs = vec![PathBuf::from("monday/call.wav"), PathBuf::from("tuesday/call.wav")];
+        let same_dir = vec![PathBuf::from("call.wav"), PathBuf::from("call.mp3")];
+        for (inputs, out_dir) in [(across_dirs, Some(Path::new("out"))), (same_dir, None)] {
+            let result = output_paths(&inputs, out_dir, OutputFormat::Json);
+            assert!(matches!(result, Err(SttError::InvalidInput(_))), "{inputs:?}");
+        }
+    }
+}
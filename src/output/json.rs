@@ -0,0 +1,36 @@
+//! Machine-readable transcript output.
+
+use serde::Serialize;
+
+use crate::transcriber::{RunInfo, Segment, Transcript};
+
+#[derive(Serialize)]
+struct JsonTranscript<'a> {
+    #[serde(flatten)]
+    run: &'a RunInfo,
+    text: String,
+    segments: &'a [Segment],
+}
+
+#[derive(Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum JsonLine<'a> {
+    Run(&'a RunInfo),
+    Segment(&'a Segment),
+}
+
+/// A single JSON document with the run metadata and every segment.
+pub fn render_json(transcript: &Transcript) -> String {
+    let document = JsonTranscript { run: &transcript.run, text: transcript.text(), segments: &transcript.segments };
+    let mut out = serde_json::to_string_pretty(&document).expect("transcript serialises to JSON");
+    out.push('\n');
+    out
+}
+
+/// JSON Lines: a `{"type":"run"}` header followed by one `{"type":"segment"}` line per segment.
+pub fn render_jsonl(transcript: &Transcript) -> String {
+    std::iter::once(JsonLine::Run(&transcript.run))
+        .chain(transcript.segments.iter().map(JsonLine::Segment))
+        .map(|line| serde_json::to_string(&line).expect("transcript serialises to JSON") + "\n")
+        .collect()
+}
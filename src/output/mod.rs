@@ -0,0 +1,76 @@
+//! Rendering transcripts into the formats the CLI and services emit.
+
+mod json;
+mod subtitle;
+mod text;
+
+pub use json::{render_json, render_jsonl};
+pub use subtitle::{SubtitleOptions, render_srt, render_vtt};
+pub use text::{render_plain, render_text};
+
+use crate::transcriber::Transcript;
+
+/// Every transcript format `ruststt` can write.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum OutputFormat {
+    /// `[start - end]: text` lines.
+    #[default]
+    Text,
+    /// Transcript text only, one segment per line.
+    Plain,
+    /// SubRip subtitles.
+    Srt,
+    /// WebVTT subtitles.
+    Vtt,
+    /// One JSON document with run metadata and all segments.
+    Json,
+    /// JSON Lines: a run header, then one object per segment.
+    Jsonl,
+}
+
+impl OutputFormat {
+    /// File extension used when writing this format to disk.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Text | OutputFormat::Plain => "txt",
+            OutputFormat::Srt => "srt",
+            OutputFormat::Vtt => "vtt",
+            OutputFormat::Json => "json",
+            OutputFormat::Jsonl => "jsonl",
+        }
+    }
+}
+
+impl std::str::FromStr for OutputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(OutputFormat::Text),
+            "plain" | "txt" => Ok(OutputFormat::Plain),
+            "srt" => Ok(OutputFormat::Srt),
+            "vtt" | "webvtt" => Ok(OutputFormat::Vtt),
+            "json" => Ok(OutputFormat::Json),
+            "jsonl" | "ndjson" => Ok(OutputFormat::Jsonl),
+            other => Err(format!("unknown output format '{}' (expected text, plain, srt, vtt, json or jsonl)", other)),
+        }
+    }
+}
+
+/// Format-specific settings for [`render`].
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RenderOptions {
+    pub subtitles: SubtitleOptions,
+}
+
+/// Renders `transcript` in `format`.
+pub fn render(transcript: &Transcript, format: OutputFormat, options: &RenderOptions) -> String {
+    match format {
+        OutputFormat::Text => render_text(transcript),
+        OutputFormat::Plain => render_plain(transcript),
+        OutputFormat::Srt => render_srt(transcript, &options.subtitles),
+        OutputFormat::Vtt => render_vtt(transcript, &options.subtitles),
+        OutputFormat::Json => render_json(transcript),
+        OutputFormat::Jsonl => render_jsonl(transcript),
+    }
+}
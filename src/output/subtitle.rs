@@ -0,0 +1,247 @@
+//! SubRip and WebVTT writers.
+//!
+//! Whisper segments are often longer than is comfortable to read in one
+//! caption, so each segment is word-wrapped into cues of at most
+//! `max_lines` lines of `max_line_chars` characters. The segment's time span
+//! is shared between its cues in proportion to their length, and no cue is
+//! left on screen longer than `max_cue_ms`.
+//!
+//! Diarized cues name their speaker: SRT prefixes the first line with
+//! `SPEAKER_00: `, WebVTT uses a `<v SPEAKER_00>` voice span.
+
+use std::fmt::Write as _;
+
+use crate::transcriber::Transcript;
+
+/// Layout limits applied to every cue.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct SubtitleOptions {
+    /// Maximum characters per caption line. A single longer word is kept whole.
+    pub max_line_chars: usize,
+    /// Maximum lines per cue.
+    pub max_lines: usize,
+    /// Maximum time a cue stays on screen, in milliseconds.
+    pub max_cue_ms: i64,
+}
+
+impl Default for SubtitleOptions {
+    fn default() -> Self {
+        Self { max_line_chars: 42, max_lines: 2, max_cue_ms: 7000 }
+    }
+}
+
+/// A word laid out in a cue, with its timing when word timestamps are known.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct CueWord {
+    text: String,
+    start_ms: Option<i64>,
+    end_ms: Option<i64>,
+}
+
+/// One caption: an on-screen time span and its wrapped lines.
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Cue {
+    start_ms: i64,
+    end_ms: i64,
+    lines: Vec<Vec<CueWord>>,
+    speaker: Option<String>,
+}
+
+fn line_chars(line: &[CueWord]) -> usize {
+    line.iter().map(|w| w.text.chars().count()).sum::<usize>() + line.len().saturating_sub(1)
+}
+
+/// Greedy word wrap into lines of at most `width` characters.
+fn wrap(words: Vec<CueWord>, width: usize) -> Vec<Vec<CueWord>> {
+    let mut lines = Vec::new();
+    let mut line: Vec<CueWord> = Vec::new();
+    for word in words {
+        if !line.is_empty() && line_chars(&line) + 1 + word.text.chars().count() > width {
+            lines.push(std::mem::take(&mut line));
+        }
+        line.push(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+fn build_cues(transcript: &Transcript, options: &SubtitleOptions) -> Vec<Cue> {
+    let max_lines = options.max_lines.max(1);
+    let mut cues = Vec::new();
+    for segment in &transcript.segments {
+        let words: Vec<CueWord> = if segment.words.is_empty() {
+            segment.text
+                .split_whitespace()
+                .map(|w| CueWord { text: w.to_string(), start_ms: None, end_ms: None })
+                .collect()
+        } else {
+            segment.words
+                .iter()
+                .map(|w| CueWord { text: w.text.clone(), start_ms: Some(w.start_ms), end_ms: Some(w.end_ms) })
+                .collect()
+        };
+        let lines = wrap(words, options.max_line_chars.max(1));
+        let total_chars: usize = lines.iter().map(|l| line_chars(l)).sum();
+        if total_chars == 0 {
+            continue;
+        }
+
+        // Without word timing, the segment's span is shared out by length.
+        let span = (segment.end_ms - segment.start_ms).max(0);
+        let mut consumed = 0usize;
+        for group in lines.chunks(max_lines) {
+            let proportional_start = segment.start_ms + span * consumed as i64 / total_chars as i64;
+            consumed += group.iter().map(|l| line_chars(l)).sum::<usize>();
+            let proportional_end = segment.start_ms + span * consumed as i64 / total_chars as i64;
+
+            let start_ms = group[0][0].start_ms.unwrap_or(proportional_start);
+            let end_ms = group.last().and_then(|l| l.last()).and_then(|w| w.end_ms).unwrap_or(proportional_end).max(start_ms);
+            cues.push(Cue {
+                start_ms,
+                end_ms: end_ms.min(start_ms + options.max_cue_ms.max(1)),
+                lines: group.to_vec(),
+                speaker: segment.speaker.clone(),
+            });
+        }
+    }
+    cues
+}
+
+/// `HH:MM:SS<sep>mmm`.
+fn timestamp(ms: i64, separator: char) -> String {
+    let ms = ms.max(0);
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        ms / 3_600_000,
+        ms / 60_000 % 60,
+        ms / 1000 % 60,
+        separator,
+        ms % 1000
+    )
+}
+
+/// Renders SubRip (`.srt`) subtitles.
+pub fn render_srt(transcript: &Transcript, options: &SubtitleOptions) -> String {
+    let mut out = String::new();
+    for (index, cue) in build_cues(transcript, options).iter().enumerate() {
+        let _ = writeln!(out, "{}", index + 1);
+        let _ = writeln!(out, "{} --> {}", timestamp(cue.start_ms, ','), timestamp(cue.end_ms, ','));
+        for (number, line) in cue.lines.iter().enumerate() {
+            let words: Vec<&str> = line.iter().map(|w| w.text.as_str()).collect();
+            let _ = match (&cue.speaker, number) {
+                (Some(speaker), 0) => writeln!(out, "{}: {}", speaker, words.join(" ")),
+                _ => writeln!(out, "{}", words.join(" ")),
+            };
+        }
+        out.push('\n');
+    }
+    out
+}
+
+/// Escapes cue text for WebVTT, where `&` starts a character reference and
+/// `<` opens a tag. Escaping `>` also keeps `-->` out of the cue payload.
+fn escape_vtt(text: &str) -> String {
+    text.replace('&', "&amp;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+/// Renders WebVTT (`.vtt`) subtitles.
+///
+/// With word timing, every word after the first in a cue is preceded by a
+/// `<HH:MM:SS.mmm>` timestamp tag so players can highlight it as it is spoken.
+pub fn render_vtt(transcript: &Transcript, options: &SubtitleOptions) -> String {
+    let mut out = String::from("WEBVTT\n\n");
+    for cue in build_cues(transcript, options) {
+        let _ = writeln!(out, "{} --> {}", timestamp(cue.start_ms, '.'), timestamp(cue.end_ms, '.'));
+        let mut first = true;
+        for line in &cue.lines {
+            let mut text = String::new();
+            if first && let Some(speaker) = &cue.speaker {
+                let _ = write!(text, "<v {}>", escape_vtt(speaker));
+            }
+            for word in line {
+                if !first && !text.is_empty() {
+                    text.push(' ');
+                }
+                if let (false, Some(start_ms)) = (first, word.start_ms) {
+                    let _ = write!(text, "<{}>", timestamp(start_ms.clamp(cue.start_ms, cue.end_ms), '.'));
+                }
+                first = false;
+                text.push_str(&escape_vtt(&word.text));
+            }
+            let _ = writeln!(out, "{}", text);
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transcriber::Segment;
+    use crate::words::Word;
+
+    fn word(text: &str) -> CueWord {
+        CueWord { text: text.to_string(), start_ms: None, end_ms: None }
+    }
+
+    fn transcript(segments: Vec<Segment>) -> Transcript {
+        Transcript { segments, ..Default::default() }
+    }
+
+    #[test]
+    fn timestamp_formats_hours_and_clamps_negative_times() {
+        assert_eq!(timestamp(3_723_004, ','), "01:02:03,004");
+        assert_eq!(timestamp(59_999, '.'), "00:00:59.999");
+        assert_eq!(timestamp(-5, '.'), "00:00:00.000");
+    }
+
+    #[test]
+    fn wrap_breaks_before_the_width_and_keeps_long_words_whole() {
+        let words = ["one", "two", "three", "extraordinarily", "a"].map(word).to_vec();
+        let lines: Vec<Vec<String>> = wrap(words, 9)
+            .into_iter()
+            .map(|line| line.into_iter().map(|w| w.text).collect())
+            .collect();
+        assert_eq!(lines, vec![vec!["one", "two"], vec!["three"], vec!["extraordinarily"], vec!["a"]]);
+    }
+
+    #[test]
+    fn build_cues_shares_an_untimed_segment_by_length() {
+        let segment = Segment { start_ms: 1_000, end_ms: 3_000, text: " aaaa bbbb cccc dddd".into(), ..Default::default() };
+        let options = SubtitleOptions { max_line_chars: 9, max_lines: 1, max_cue_ms: 7_000 };
+        let spans: Vec<(i64, i64)> = build_cues(&transcript(vec![segment]), &options)
+            .iter()
+            .map(|cue| (cue.start_ms, cue.end_ms))
+            .collect();
+        assert_eq!(spans, vec![(1_000, 2_000), (2_000, 3_000)]);
+    }
+
+    #[test]
+    fn build_cues_uses_word_timing_and_caps_cue_length() {
+        let words = vec![
+            Word { text: "hello".into(), start_ms: 500, end_ms: 900, probability: 1.0 },
+            Word { text: "world".into(), start_ms: 1_000, end_ms: 9_000, probability: 1.0 },
+        ];
+        let segment = Segment { start_ms: 0, end_ms: 9_000, text: " hello world".into(), words, ..Default::default() };
+        let options = SubtitleOptions { max_cue_ms: 2_000, ..Default::default() };
+        let cues = build_cues(&transcript(vec![segment]), &options);
+        assert_eq!(cues.len(), 1);
+        assert_eq!((cues[0].start_ms, cues[0].end_ms), (500, 2_500));
+    }
+
+    #[test]
+    fn vtt_escapes_cue_text_and_speaker_names() {
+        let segment = Segment {
+            start_ms: 0,
+            end_ms: 1_000,
+            text: " Q&A <b> -->".into(),
+            speaker: Some("Tom & <Jerry>".into()),
+            ..Default::default()
+        };
+        let vtt = render_vtt(&transcript(vec![segment]), &SubtitleOptions::default());
+        assert!(vtt.contains("<v Tom &amp; &lt;Jerry&gt;>Q&amp;A &lt;b&gt; --&gt;\n"), "{vtt}");
+    }
+}
@@ -0,0 +1,39 @@
+use std::fmt::Write as _;
+
+use crate::transcriber::Transcript;
+
+/// `[start - end]: text` lines with timestamps in seconds, each followed by
+/// indented `[start - end] word (probability)` lines when words are present.
+/// Diarized segments read `[start - end] SPEAKER_00: text`.
+pub fn render_text(transcript: &Transcript) -> String {
+    let mut out = String::new();
+    for segment in &transcript.segments {
+        let _ = writeln!(out, "[{:.2}s - {:.2}s]{} {}",
+            segment.start_ms as f64 / 1000.0,
+            segment.end_ms as f64 / 1000.0,
+            segment.speaker.as_deref().map(|s| format!(" {}:", s)).unwrap_or_else(|| ":".to_string()),
+            segment.text
+        );
+        for word in &segment.words {
+            let _ = writeln!(out, "    [{:.2}s - {:.2}s] {} ({:.2})",
+                word.start_ms as f64 / 1000.0,
+                word.end_ms as f64 / 1000.0,
+                word.text,
+                word.probability
+            );
+        }
+    }
+    out
+}
+
+/// Segment text only, one segment per line, prefixed with the speaker when diarized.
+pub fn render_plain(transcript: &Transcript) -> String {
+    let mut out = String::new();
+    for segment in &transcript.segments {
+        let _ = match &segment.speaker {
+            Some(speaker) => writeln!(out, "{}: {}", speaker, segment.text),
+            None => writeln!(out, "{}", segment.text),
+        };
+    }
+    out
+}
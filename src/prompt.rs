@@ -0,0 +1,80 @@
+//! Biasing the decoder with an initial prompt.
+//!
+//! Whisper conditions on up to half its text context (224 tokens) of prior
+//! text and drops the oldest tokens beyond that. The prompt is assembled
+//! oldest-first as glossary, user prompt, then carried context, and the
+//! carried context is capped so it cannot push the glossary out.
+
+use std::path::Path;
+
+use crate::error::Result;
+
+/// Longest carried context, in characters (roughly 100 tokens).
+pub(crate) const MAX_CONTEXT_CHARS: usize = 400;
+
+/// Reads a term list: one term per line, blank lines and `#` comments ignored.
+pub fn load_vocabulary(path: impl AsRef<Path>) -> Result<Vec<String>> {
+    let text = std::fs::read_to_string(path)?;
+    Ok(text
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .map(str::to_string)
+        .collect())
+}
+
+/// Combines vocabulary, a user prompt and previously transcribed text into a
+/// single prompt, or `None` when all are empty.
+pub fn build_prompt(vocabulary: &[String], initial_prompt: Option<&str>, context: Option<&str>) -> Option<String> {
+    let mut parts = Vec::new();
+    if !vocabulary.is_empty() {
+        parts.push(format!("Glossary: {}.", vocabulary.join(", ")));
+    }
+    if let Some(prompt) = initial_prompt.map(str::trim).filter(|p| !p.is_empty()) {
+        parts.push(prompt.to_string());
+    }
+    if let Some(context) = context.map(|c| tail(c.trim(), MAX_CONTEXT_CHARS)).filter(|c| !c.is_empty()) {
+        parts.push(context.to_string());
+    }
+    (!parts.is_empty()).then(|| parts.join(" "))
+}
+
+/// The last whole words of `text` fitting in `max_chars` characters.
+fn tail(text: &str, max_chars: usize) -> &str {
+    let count = text.chars().count();
+    if count <= max_chars {
+        return text;
+    }
+    let (start, _) = text.char_indices().nth(count - max_chars).expect("index within text");
+    let rest = &text[start..];
+    match rest.find(char::is_whitespace) {
+        Some(space) => rest[space..].trim_start(),
+        None => rest,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tail_keeps_only_whole_trailing_words() {
+        assert_eq!(tail("short text", 20), "short text");
+        assert_eq!(tail("the quick brown fox", 12), "brown fox");
+        assert_eq!(tail("über straße grün", 9), "grün");
+        // A single word longer than the limit is cut rather than dropped.
+        assert_eq!(tail("antidisestablishment", 5), "hment");
+    }
+
+    #[test]
+    fn build_prompt_orders_glossary_prompt_then_context() {
+        let vocabulary = vec!["Kubernetes".to_string(), "etcd".to_string()];
+        assert_eq!(
+            build_prompt(&vocabulary, Some(" A talk. "), Some(" and then ")).as_deref(),
+            Some("Glossary: Kubernetes, etcd. A talk. and then")
+        );
+        assert_eq!(build_prompt(&[], Some("  "), Some("")), None);
+        let long = "word ".repeat(200);
+        assert!(build_prompt(&[], None, Some(&long)).unwrap().len() <= MAX_CONTEXT_CHARS);
+    }
+}
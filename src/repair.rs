@@ -0,0 +1,82 @@
+//! Last-resort recovery of WAV files hound cannot parse, via an external ffmpeg.
+//!
+//! The source file is only ever read. Repairs land in a scratch file that is
+//! removed afterwards, unless the caller explicitly asks for the original to be
+//! replaced.
+
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::{self, Command};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::error::{Result, SttError};
+
+/// Deletes the scratch file when dropped.
+pub(crate) struct RepairedFile {
+    path: PathBuf,
+    keep: bool,
+}
+
+impl RepairedFile {
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Atomically replaces `original` with the repaired file.
+    pub(crate) fn replace(mut self, original: &Path) -> Result<()> {
+        fs::rename(&self.path, original)?;
+        self.keep = true;
+        eprintln!("Replaced '{}' with the repaired copy.", original.display());
+        Ok(())
+    }
+}
+
+impl Drop for RepairedFile {
+    fn drop(&mut self) {
+        if !self.keep {
+            let _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+fn scratch_path(input: &Path, in_place: bool) -> PathBuf {
+    if in_place {
+        // Same directory, so the final rename cannot cross filesystems.
+        return input.with_extension("repaired.tmp.wav");
+    }
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let stem = input.file_stem().map(|s| s.to_string_lossy().into_owned()).unwrap_or_default();
+    std::env::temp_dir().join(format!(
+        "ruststt-{}-{}-{}.wav",
+        process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed),
+        stem
+    ))
+}
+
+/// Re-muxes `input` with ffmpeg into a scratch WAV file.
+///
+/// `in_place` only controls where the scratch file is created; the caller
+/// decides whether to [`RepairedFile::replace`] the original.
+pub(crate) fn repair_with_ffmpeg(input: &Path, in_place: bool) -> Result<RepairedFile> {
+    eprintln!("Attempting to repair '{}' with ffmpeg...", input.display());
+
+    let repaired = RepairedFile { path: scratch_path(input, in_place), keep: false };
+
+    let output = Command::new("ffmpeg")
+        .arg("-i")
+        .arg(input)
+        .arg("-c:a")
+        .arg("copy")
+        .arg("-y")
+        .arg(repaired.path())
+        .output()
+        .map_err(|e| SttError::Repair(format!("failed to run ffmpeg. Is ffmpeg installed and in your PATH? ({})", e)))?;
+
+    if !output.status.success() {
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        return Err(SttError::Repair(format!("ffmpeg failed to repair the file.\nffmpeg stderr: {}", stderr)));
+    }
+
+    Ok(repaired)
+}
@@ -0,0 +1,299 @@
+//! Band-limited sample-rate conversion.
+//!
+//! Whisper only understands 16 kHz audio, so every decoded input passes through
+//! [`resample`] first. The converter is a rational polyphase resampler: the
+//! input/output ratio is reduced to `up / down`, and one Kaiser-windowed sinc
+//! filter is precomputed per output phase.
+
+use std::f64::consts::PI;
+
+use serde::Serialize;
+
+/// The sample rate Whisper models are trained on.
+pub const WHISPER_SAMPLE_RATE: u32 = 16_000;
+
+/// Trade-off between resampling speed and stop-band rejection.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ResampleQuality {
+    /// 8 zero crossings per side, roughly 60 dB rejection.
+    Fast,
+    /// 16 zero crossings per side, roughly 80 dB rejection.
+    #[default]
+    Medium,
+    /// 32 zero crossings per side, roughly 100 dB rejection.
+    High,
+}
+
+impl ResampleQuality {
+    fn zero_crossings(self) -> usize {
+        match self {
+            ResampleQuality::Fast => 8,
+            ResampleQuality::Medium => 16,
+            ResampleQuality::High => 32,
+        }
+    }
+
+    fn kaiser_beta(self) -> f64 {
+        match self {
+            ResampleQuality::Fast => 5.7,
+            ResampleQuality::Medium => 8.0,
+            ResampleQuality::High => 10.0,
+        }
+    }
+
+    /// Fraction of the Nyquist frequency kept in the pass band.
+    fn rolloff(self) -> f64 {
+        match self {
+            ResampleQuality::Fast => 0.90,
+            ResampleQuality::Medium => 0.94,
+            ResampleQuality::High => 0.97,
+        }
+    }
+}
+
+impl std::str::FromStr for ResampleQuality {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "fast" => Ok(ResampleQuality::Fast),
+            "medium" => Ok(ResampleQuality::Medium),
+            "high" => Ok(ResampleQuality::High),
+            other => Err(format!("unknown resample quality '{}' (expected fast, medium or high)", other)),
+        }
+    }
+}
+
+fn gcd(mut a: u64, mut b: u64) -> u64 {
+    while b != 0 {
+        (a, b) = (b, a % b);
+    }
+    a
+}
+
+/// Zeroth-order modified Bessel function of the first kind.
+fn bessel_i0(x: f64) -> f64 {
+    let mut sum = 1.0;
+    let mut term = 1.0;
+    let half = x / 2.0;
+    for k in 1..64 {
+        term *= (half / k as f64) * (half / k as f64);
+        sum += term;
+        if term < sum * 1e-12 {
+            break;
+        }
+    }
+    sum
+}
+
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1e-12 { 1.0 } else { (PI * x).sin() / (PI * x) }
+}
+
+/// A precomputed polyphase filter bank for one `from -> to` conversion.
+pub struct Resampler {
+    up: u64,
+    down: u64,
+    /// Taps per phase.
+    width: usize,
+    /// `up` phases of `width` taps each; tap `k` of a phase multiplies
+    /// input sample `base + k - (width / 2 - 1)`.
+    taps: Vec<f32>,
+}
+
+impl Resampler {
+    pub fn new(from_rate: u32, to_rate: u32, quality: ResampleQuality) -> Self {
+        assert!(from_rate > 0 && to_rate > 0, "sample rates must be non-zero");
+        let g = gcd(from_rate as u64, to_rate as u64);
+        let up = to_rate as u64 / g;
+        let down = from_rate as u64 / g;
+
+        // When decimating, the cutoff moves down to the output Nyquist and the
+        // kernel stretches accordingly.
+        let cutoff = (up as f64 / down as f64).min(1.0) * quality.rolloff();
+        let half = (quality.zero_crossings() as f64 / cutoff).ceil() as usize;
+        let width = 2 * half;
+        let beta = quality.kaiser_beta();
+        let i0_beta = bessel_i0(beta);
+
+        let mut taps = Vec::with_capacity(up as usize * width);
+        for phase in 0..up {
+            let frac = phase as f64 / up as f64;
+            let start = taps.len();
+            let mut sum = 0.0;
+            for k in 0..width {
+                // Distance from the output instant to this input sample.
+                let d = (k as f64 - (half as f64 - 1.0)) - frac;
+                let r = d / half as f64;
+                let window = if r.abs() >= 1.0 { 0.0 } else { bessel_i0(beta * (1.0 - r * r).sqrt()) / i0_beta };
+                let h = cutoff * sinc(cutoff * d) * window;
+                sum += h;
+                taps.push(h as f32);
+            }
+            // Unity DC gain for every phase.
+            if sum.abs() > f64::EPSILON {
+                for tap in &mut taps[start..] {
+                    *tap = (*tap as f64 / sum) as f32;
+                }
+            }
+        }
+
+        Self { up, down, width, taps }
+    }
+
+    /// Number of output samples produced for `input_len` input samples.
+    pub fn output_len(&self, input_len: usize) -> usize {
+        ((input_len as u64 * self.up).div_ceil(self.down)) as usize
+    }
+
+    /// Converts a whole mono buffer.
+    pub fn process(&self, input: &[f32]) -> Vec<f32> {
+        if self.up == self.down {
+            return input.to_vec();
+        }
+        let n_out = self.output_len(input.len());
+        let offset = (self.width / 2 - 1) as i64;
+        let mut output = Vec::with_capacity(n_out);
+        for n in 0..n_out as u64 {
+            let pos = n * self.down;
+            let base = (pos / self.up) as i64;
+            let phase = (pos % self.up) as usize;
+            let filter = &self.taps[phase * self.width..(phase + 1) * self.width];
+            let first = base - offset;
+            let mut acc = 0.0f32;
+            for (k, tap) in filter.iter().enumerate() {
+                let i = first + k as i64;
+                if i >= 0 && (i as usize) < input.len() {
+                    acc += input[i as usize] * tap;
+                }
+            }
+            output.push(acc);
+        }
+        output
+    }
+}
+
+/// Incremental wrapper around [`Resampler`] for audio that arrives in chunks.
+///
+/// Output is identical to converting the concatenated input in one go; the
+/// last few milliseconds are held back until [`StreamResampler::flush`].
+pub struct StreamResampler {
+    resampler: Resampler,
+    /// Input samples not yet fully consumed, starting at absolute index `history_start`.
+    history: Vec<f32>,
+    history_start: u64,
+    /// Absolute index of the next output sample.
+    next_output: u64,
+}
+
+impl StreamResampler {
+    pub fn new(from_rate: u32, to_rate: u32, quality: ResampleQuality) -> Self {
+        Self {
+            resampler: Resampler::new(from_rate, to_rate, quality),
+            history: Vec::new(),
+            history_start: 0,
+            next_output: 0,
+        }
+    }
+
+    /// Feeds `input` and returns every output sample it completes.
+    pub fn process(&mut self, input: &[f32]) -> Vec<f32> {
+        self.history.extend_from_slice(input);
+        self.drain(false)
+    }
+
+    /// Emits the held-back tail, treating the input as ended.
+    pub fn flush(&mut self) -> Vec<f32> {
+        self.drain(true)
+    }
+
+    fn drain(&mut self, end_of_input: bool) -> Vec<f32> {
+        let r = &self.resampler;
+        if r.up == r.down {
+            self.history_start += self.history.len() as u64;
+            return std::mem::take(&mut self.history);
+        }
+        let offset = (r.width / 2 - 1) as i64;
+        let available = self.history_start + self.history.len() as u64;
+        let total_out = r.output_len(available as usize) as u64;
+
+        let mut output = Vec::new();
+        while self.next_output < total_out {
+            let pos = self.next_output * r.down;
+            let base = (pos / r.up) as i64;
+            let first = base - offset;
+            let last = first + r.width as i64 - 1;
+            if !end_of_input && last >= available as i64 {
+                break;
+            }
+            let phase = (pos % r.up) as usize;
+            let filter = &r.taps[phase * r.width..(phase + 1) * r.width];
+            let mut acc = 0.0f32;
+            for (k, tap) in filter.iter().enumerate() {
+                let i = first + k as i64;
+                if i >= self.history_start as i64 && (i as u64) < available {
+                    acc += self.history[(i as u64 - self.history_start) as usize] * tap;
+                }
+            }
+            output.push(acc);
+            self.next_output += 1;
+        }
+
+        // Keep only what the next output sample still needs.
+        let needed_from = ((self.next_output * r.down / r.up) as i64 - offset).max(0) as u64;
+        if needed_from > self.history_start {
+            let drop = ((needed_from - self.history_start) as usize).min(self.history.len());
+            self.history.drain(..drop);
+            self.history_start += drop as u64;
+        }
+        output
+    }
+}
+
+/// Converts mono `samples` from `from_rate` to `to_rate`.
+pub fn resample(samples: &[f32], from_rate: u32, to_rate: u32, quality: ResampleQuality) -> Vec<f32> {
+    if from_rate == to_rate {
+        return samples.to_vec();
+    }
+    Resampler::new(from_rate, to_rate, quality).process(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn output_length_follows_the_rate_ratio() {
+        assert_eq!(resample(&vec![0.0; 44_100], 44_100, 16_000, ResampleQuality::Medium).len(), 16_000);
+        assert_eq!(resample(&vec![0.0; 8_000], 8_000, 16_000, ResampleQuality::Fast).len(), 16_000);
+        // A partial final period still produces its first output sample.
+        assert_eq!(Resampler::new(48_000, 16_000, ResampleQuality::Medium).output_len(4), 2);
+        assert_eq!(resample(&[0.5, -0.5], 16_000, 16_000, ResampleQuality::High), vec![0.5, -0.5]);
+    }
+
+    #[test]
+    fn dc_passes_with_unity_gain() {
+        for (from, quality) in [(44_100, ResampleQuality::Fast), (48_000, ResampleQuality::Medium), (8_000, ResampleQuality::High)] {
+            let output = resample(&vec![0.25; from as usize], from, WHISPER_SAMPLE_RATE, quality);
+            // Away from the edges, where the filter runs off the input.
+            for &sample in &output[200..output.len() - 200] {
+                assert!((sample - 0.25).abs() < 1e-4, "{from} Hz {quality:?}: {sample}");
+            }
+        }
+    }
+
+    #[test]
+    fn streaming_matches_one_shot_conversion() {
+        let input: Vec<f32> = (0..22_050).map(|i| (i as f32 * 0.037).sin() * 0.8).collect();
+        let one_shot = Resampler::new(22_050, 16_000, ResampleQuality::Medium).process(&input);
+
+        let mut stream = StreamResampler::new(22_050, 16_000, ResampleQuality::Medium);
+        let mut streamed = Vec::new();
+        for chunk in input.chunks(1_013) {
+            streamed.extend(stream.process(chunk));
+        }
+        streamed.extend(stream.flush());
+        assert_eq!(streamed, one_shot);
+    }
+}
@@ -0,0 +1,541 @@
+//! A JSON-RPC 2.0 worker on stdin and stdout, for hosts that spawn `ruststt`
+//! as a child process.
+//!
+//! Requests and responses are one JSON object per line. Stdout carries
+//! nothing else; every log line goes to stderr.
+//!
+//! ```text
+//! <- {"jsonrpc":"2.0","method":"ready","params":{"version":"0.1.0"}}
+//! -> {"jsonrpc":"2.0","id":1,"method":"loadModel","params":{"path":"models/ggml-base.en.bin"}}
+//! <- {"jsonrpc":"2.0","id":1,"result":{"model":"models/ggml-base.en.bin"}}
+//! -> {"jsonrpc":"2.0","id":2,"method":"transcribe","params":{"path":"call.wav","format":"srt"}}
+//! <- {"jsonrpc":"2.0","method":"progress","params":{"id":2,"processed_ms":30000,"total_ms":95000}}
+//! <- {"jsonrpc":"2.0","id":2,"result":{"format":"srt","content":"1\n00:00:00,000 --> ..."}}
+//! -> {"jsonrpc":"2.0","id":3,"method":"shutdown"}
+//! <- {"jsonrpc":"2.0","id":3,"result":null}
+//! ```
+//!
+//! Up to `jobs` transcriptions run at once, each on a job thread with its own
+//! decoder state; further `transcribe` requests wait in a queue. Requests
+//! are read while transcriptions run, so `cancel` stops a running or queued
+//! one. `loadModel` is handled in order: requests after it use the new
+//! model, runs already queued finish on the old one. Without a `loadModel`
+//! the model given at startup is loaded by the first `transcribe`.
+
+use std::collections::HashMap;
+use std::io::{self, BufRead, Write};
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use serde::Deserialize;
+use serde_json::{json, Value};
+
+use crate::batch;
+use crate::control::RunControl;
+use crate::diarize::DiarizeOptions;
+use crate::error::{Result, SttError};
+use crate::output::{self, OutputFormat, RenderOptions};
+use crate::transcriber::{ModelOptions, TranscribeOptions, Transcriber};
+
+/// Error code of a `transcribe` stopped by `cancel`.
+pub const CANCELLED: i64 = -32800;
+/// Error code of a failed `transcribe` or `loadModel`; `data.kind` names the [`SttError`].
+pub const FAILED: i64 = -32000;
+
+const PARSE_ERROR: i64 = -32700;
+const INVALID_REQUEST: i64 = -32600;
+const METHOD_NOT_FOUND: i64 = -32601;
+const INVALID_PARAMS: i64 = -32602;
+
+/// Model and concurrency settings for [`serve`].
+#[derive(Clone, Debug, PartialEq)]
+pub struct RpcOptions {
+    /// Model loaded by the first `transcribe` unless `loadModel` picks another.
+    pub model: PathBuf,
+    pub model_options: ModelOptions,
+    /// Transcriptions run at once; further requests are queued.
+    pub jobs: usize,
+}
+
+impl Default for RpcOptions {
+    fn default() -> Self {
+        Self { model: PathBuf::from("models/ggml-base.en.bin"), model_options: ModelOptions::default(), jobs: batch::default_jobs() }
+    }
+}
+
+/// Runs the worker on the process's stdin and stdout until `shutdown` or the
+/// end of stdin.
+pub fn serve(options: &TranscribeOptions, render_options: &RenderOptions, rpc_options: &RpcOptions) -> Result<()> {
+    run(io::stdin().lock(), io::stdout(), options, render_options, rpc_options)
+}
+
+/// Like [`serve`], reading requests from `input` and writing to `output`.
+///
+/// Every `transcribe` starts from `options`; its params override the
+/// language, task, prompt and a few other fields. When `options.threads` is
+/// `None` the physical cores are split between the jobs. Running and queued
+/// transcriptions are cancelled on `shutdown` and when `input` ends.
+pub fn run(input: impl BufRead, output: impl Write + Send, options: &TranscribeOptions, render_options: &RenderOptions, rpc_options: &RpcOptions) -> Result<()> {
+    let jobs = rpc_options.jobs.max(1);
+    let options = TranscribeOptions { threads: Some(options.threads.unwrap_or_else(|| batch::split_threads(jobs))), ..options.clone() };
+    let (sender, receiver) = mpsc::channel();
+    let (queue, queued) = mpsc::channel();
+    let queued = Mutex::new(queued);
+    let worker = Worker {
+        default_model: &rpc_options.model,
+        model_options: &rpc_options.model_options,
+        options: &options,
+        render_options,
+        model: Mutex::new(None),
+        runs: Mutex::new(HashMap::new()),
+        queue: Mutex::new(Some(queue)),
+        out: sender,
+    };
+    let (worker, queued) = (&worker, &queued);
+    thread::scope(|scope| {
+        let writer = scope.spawn(move || write_lines(output, receiver));
+        let pool: Vec<_> = (0..jobs).map(|_| scope.spawn(move || worker.run_jobs(queued))).collect();
+        eprintln!("Worker ready with {} job thread(s)", jobs);
+        worker.send(json!({ "jsonrpc": "2.0", "method": "ready", "params": { "version": env!("CARGO_PKG_VERSION") } }));
+
+        let mut shutdown = None;
+        for line in input.lines() {
+            let line = match line {
+                Ok(line) => line,
+                Err(e) => {
+                    eprintln!("Failed to read request: {}", e);
+                    break;
+                }
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match worker.dispatch(&line) {
+                Dispatch::Done => {}
+                Dispatch::Shutdown(id) => {
+                    shutdown = Some(id);
+                    break;
+                }
+            }
+        }
+
+        let cancelled = worker.cancel_all();
+        if cancelled > 0 {
+            eprintln!("Cancelling {} running or queued transcription(s)", cancelled);
+        }
+        // Closing the queue lets the job threads exit once it is drained.
+        worker.queue.lock().unwrap().take();
+        for job in pool {
+            let _ = job.join();
+        }
+        if let Some(Some(id)) = shutdown {
+            worker.respond(id, Ok(Value::Null));
+        }
+        let _ = worker.out.send(None);
+        writer.join().expect("the output thread does not panic")
+    })
+}
+
+/// Writes each message as one line until told to stop.
+fn write_lines(mut output: impl Write, receiver: mpsc::Receiver<Option<Value>>) -> Result<()> {
+    let mut result = Ok(());
+    while let Ok(Some(message)) = receiver.recv() {
+        // Keep draining after a failed write so senders never block on a dead pipe.
+        if result.is_ok() {
+            result = writeln!(output, "{}", message).and_then(|_| output.flush());
+            if let Err(e) = &result {
+                eprintln!("Failed to write response: {}", e);
+            }
+        }
+    }
+    Ok(result?)
+}
+
+/// An error reported in a response's `error` member.
+struct RpcError {
+    code: i64,
+    message: String,
+    data: Option<Value>,
+}
+
+impl RpcError {
+    fn new(code: i64, message: impl Into<String>) -> Self {
+        RpcError { code, message: message.into(), data: None }
+    }
+}
+
+impl From<SttError> for RpcError {
+    fn from(e: SttError) -> Self {
+        let kind = match &e {
+            SttError::Cancelled => return RpcError::new(CANCELLED, e.to_string()),
+            SttError::Io(_) => "io",
+            SttError::AudioDecode { .. } => "audio_decode",
+            SttError::UnsupportedFormat(_) => "unsupported_format",
+            SttError::Repair(_) => "repair",
+            SttError::ModelLoad { .. } => "model_load",
+            SttError::Inference(_) => "inference",
+            SttError::InvalidInput(_) => "invalid_input",
+        };
+        RpcError { code: FAILED, message: e.to_string(), data: Some(json!({ "kind": kind })) }
+    }
+}
+
+#[derive(Deserialize)]
+struct Request {
+    jsonrpc: String,
+    /// Absent for notifications, which get no response.
+    id: Option<Value>,
+    method: String,
+    #[serde(default)]
+    params: Value,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LoadModelParams {
+    path: PathBuf,
+    /// DTW alignment-heads preset for word timestamps, e.g. `"auto"`.
+    dtw: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct TranscribeParams {
+    path: PathBuf,
+    /// Any `--format` value; `json` (the default) is returned as an object.
+    format: Option<String>,
+    language: Option<String>,
+    task: Option<String>,
+    prompt: Option<String>,
+    word_timestamps: Option<bool>,
+    diarize: Option<bool>,
+    speakers: Option<usize>,
+    channel: Option<String>,
+    channel_labels: Option<Vec<String>>,
+    vad: Option<String>,
+    /// Send `progress` notifications (default: true).
+    #[serde(default = "default_progress")]
+    progress: bool,
+}
+
+fn default_progress() -> bool {
+    true
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct CancelParams {
+    /// The id of the `transcribe` request to stop.
+    id: Value,
+}
+
+/// Parses a string param with its `FromStr`.
+fn parse<T: std::str::FromStr<Err = String>>(value: Option<String>) -> Result<Option<T>, RpcError> {
+    value.map(|v| v.parse().map_err(|e| RpcError::new(INVALID_PARAMS, e))).transpose()
+}
+
+fn params<T: for<'de> Deserialize<'de>>(params: Value) -> Result<T, RpcError> {
+    // Methods without required fields may be called without params.
+    let params = if params.is_null() { json!({}) } else { params };
+    serde_json::from_value(params).map_err(|e| RpcError::new(INVALID_PARAMS, format!("invalid params: {}", e)))
+}
+
+impl TranscribeParams {
+    fn options(&self, base: &TranscribeOptions) -> Result<TranscribeOptions, RpcError> {
+        let mut options = base.clone();
+        if let Some(language) = &self.language {
+            options.language = language.clone();
+        }
+        if let Some(task) = parse(self.task.clone())? {
+            options.task = task;
+        }
+        if let Some(prompt) = &self.prompt {
+            options.initial_prompt = Some(prompt.clone());
+        }
+        if let Some(words) = self.word_timestamps {
+            options.word_timestamps = words;
+        }
+        if let Some(diarize) = self.diarize {
+            options.diarize.enabled = diarize;
+        }
+        if let Some(speakers) = self.speakers {
+            options.diarize = DiarizeOptions { enabled: true, speakers: Some(speakers.max(1)), ..options.diarize };
+        }
+        if let Some(channel) = parse(self.channel.clone())? {
+            options.audio.channels = channel;
+        }
+        if let Some(labels) = &self.channel_labels {
+            options.channel_labels = labels.clone();
+        }
+        if let Some(vad) = parse(self.vad.clone())? {
+            options.vad.mode = vad;
+        }
+        Ok(options)
+    }
+}
+
+enum Dispatch {
+    Done,
+    /// `shutdown` was requested, with this id unless sent as a notification.
+    Shutdown(Option<Value>),
+}
+
+/// A validated `transcribe` request waiting for a job thread.
+struct Job {
+    id: Option<Value>,
+    params: TranscribeParams,
+    format: OutputFormat,
+    options: TranscribeOptions,
+    transcriber: Arc<Transcriber>,
+    control: RunControl,
+}
+
+struct Worker<'a> {
+    default_model: &'a Path,
+    model_options: &'a ModelOptions,
+    options: &'a TranscribeOptions,
+    render_options: &'a RenderOptions,
+    model: Mutex<Option<Arc<Transcriber>>>,
+    /// Running and queued transcriptions by the JSON text of their request id.
+    runs: Mutex<HashMap<String, RunControl>>,
+    /// Feeds the job threads; taken on shutdown.
+    queue: Mutex<Option<Sender<Job>>>,
+    /// Lines for stdout; `None` stops the writer.
+    out: Sender<Option<Value>>,
+}
+
+impl<'a> Worker<'a> {
+    fn send(&self, message: Value) {
+        let _ = self.out.send(Some(message));
+    }
+
+    fn respond(&self, id: Value, result: Result<Value, RpcError>) {
+        let message = match result {
+            Ok(result) => json!({ "jsonrpc": "2.0", "id": id, "result": result }),
+            Err(e) => {
+                let mut error = json!({ "code": e.code, "message": e.message });
+                if let Some(data) = e.data {
+                    error["data"] = data;
+                }
+                json!({ "jsonrpc": "2.0", "id": id, "error": error })
+            }
+        };
+        self.send(message);
+    }
+
+    fn dispatch(&self, line: &str) -> Dispatch {
+        let request = match serde_json::from_str::<Value>(line) {
+            Ok(value) => value,
+            Err(e) => {
+                self.respond(Value::Null, Err(RpcError::new(PARSE_ERROR, format!("parse error: {}", e))));
+                return Dispatch::Done;
+            }
+        };
+        let id = request.get("id").cloned().unwrap_or(Value::Null);
+        let request = match serde_json::from_value::<Request>(request) {
+            Ok(request) if request.jsonrpc == "2.0" => request,
+            Ok(_) => {
+                self.respond(id, Err(RpcError::new(INVALID_REQUEST, "jsonrpc must be \"2.0\"")));
+                return Dispatch::Done;
+            }
+            Err(e) => {
+                self.respond(id, Err(RpcError::new(INVALID_REQUEST, format!("invalid request: {}", e))));
+                return Dispatch::Done;
+            }
+        };
+
+        let reply = |result: Result<Value, RpcError>| {
+            if let Some(id) = &request.id {
+                self.respond(id.clone(), result);
+            }
+        };
+        match request.method.as_str() {
+            "transcribe" => {
+                if let Err(e) = self.enqueue(request.id.clone(), request.params) {
+                    reply(Err(e));
+                }
+                Dispatch::Done
+            }
+            "cancel" => {
+                reply(params(request.params).map(|p: CancelParams| json!({ "cancelled": self.cancel(&p.id) })));
+                Dispatch::Done
+            }
+            "loadModel" => {
+                reply(params(request.params).and_then(|p| self.load(p)));
+                Dispatch::Done
+            }
+            "shutdown" => Dispatch::Shutdown(request.id),
+            other => {
+                reply(Err(RpcError::new(METHOD_NOT_FOUND, format!("unknown method '{}'", other))));
+                Dispatch::Done
+            }
+        }
+    }
+
+    fn load(&self, params: LoadModelParams) -> Result<Value, RpcError> {
+        let dtw = parse(params.dtw)?.or(self.model_options.dtw);
+        let start = Instant::now();
+        let transcriber = Transcriber::with_options(&params.path, &ModelOptions { dtw })?;
+        eprintln!("Loaded model '{}' in {:.2?}", params.path.display(), start.elapsed());
+        *self.model.lock().unwrap() = Some(Arc::new(transcriber));
+        Ok(json!({ "model": params.path }))
+    }
+
+    /// The loaded model, loading the startup one on first use.
+    fn transcriber(&self) -> Result<Arc<Transcriber>> {
+        let mut model = self.model.lock().unwrap();
+        if let Some(transcriber) = model.as_ref() {
+            return Ok(transcriber.clone());
+        }
+        let transcriber = Arc::new(Transcriber::with_options(self.default_model, self.model_options)?);
+        eprintln!("Loaded model '{}'", self.default_model.display());
+        *model = Some(transcriber.clone());
+        Ok(transcriber)
+    }
+
+    /// Validates a `transcribe` request and queues it for the job threads.
+    fn enqueue(&self, id: Option<Value>, params: Value) -> Result<(), RpcError> {
+        let params: TranscribeParams = self::params(params)?;
+        let format: OutputFormat = parse(params.format.clone())?.unwrap_or(OutputFormat::Json);
+        let options = params.options(self.options)?;
+        options.validate()?;
+        let transcriber = self.transcriber()?;
+        transcriber.check_options(&options)?;
+
+        let key = id.as_ref().map(Value::to_string);
+        let control = match (&id, params.progress) {
+            (Some(id), true) => {
+                let (out, id) = (self.out.clone(), id.clone());
+                RunControl::with_progress(move |progress| {
+                    let params = json!({ "id": id, "processed_ms": progress.processed_ms, "total_ms": progress.total_ms });
+                    let _ = out.send(Some(json!({ "jsonrpc": "2.0", "method": "progress", "params": params })));
+                })
+            }
+            _ => RunControl::default(),
+        };
+        if let Some(key) = &key {
+            let mut runs = self.runs.lock().unwrap();
+            if runs.contains_key(key) {
+                return Err(RpcError::new(INVALID_REQUEST, format!("a transcription with id {} is already running", key)));
+            }
+            runs.insert(key.clone(), control.clone());
+        }
+
+        let job = Job { id, params, format, options, transcriber, control };
+        let queue = self.queue.lock().unwrap();
+        queue.as_ref().expect("the queue is open while requests are read").send(job).expect("job threads outlive the queue");
+        Ok(())
+    }
+
+    /// Runs queued transcriptions until the queue is closed.
+    fn run_jobs(&self, queued: &Mutex<Receiver<Job>>) {
+        loop {
+            let next = queued.lock().unwrap().recv();
+            match next {
+                Ok(job) => self.run_job(job),
+                Err(_) => return,
+            }
+        }
+    }
+
+    fn run_job(&self, job: Job) {
+        let Job { id, params, format, options, transcriber, control } = job;
+        let start = Instant::now();
+        let result = if control.is_cancelled() {
+            Err(SttError::Cancelled)
+        } else {
+            transcriber.context().create_state()
+                .map_err(SttError::from)
+                .and_then(|mut state| transcriber.transcribe_file_with_control(&mut state, &params.path, &options, &control))
+        };
+        if let Some(id) = &id {
+            self.runs.lock().unwrap().remove(&id.to_string());
+        }
+        match &result {
+            Ok(_) => eprintln!("Transcription of '{}' completed in {:.2?}", params.path.display(), start.elapsed()),
+            Err(e) => eprintln!("Transcription of '{}' failed: {}", params.path.display(), e),
+        }
+        if let Some(id) = id {
+            let result = result.map_err(RpcError::from).map(|transcript| match format {
+                OutputFormat::Json => serde_json::from_str(&output::render_json(&transcript)).expect("rendered JSON parses"),
+                format => json!({ "format": params.format, "content": output::render(&transcript, format, self.render_options) }),
+            });
+            self.respond(id, result);
+        }
+    }
+
+    /// Stops the running or queued transcription started by request `id`;
+    /// false if there is none.
+    fn cancel(&self, id: &Value) -> bool {
+        match self.runs.lock().unwrap().get(&id.to_string()) {
+            Some(control) => {
+                control.cancel();
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn cancel_all(&self) -> usize {
+        let runs = self.runs.lock().unwrap();
+        runs.values().for_each(RunControl::cancel);
+        runs.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feeds `requests` to [`run`] and returns every line it wrote.
+    fn round_trip(requests: &[&str]) -> Vec<Value> {
+        let input = requests.join("\n");
+        let mut output = Vec::new();
+        let rpc_options = RpcOptions { model: PathBuf::from("no-such-model.bin"), jobs: 2, ..Default::default() };
+        run(input.as_bytes(), &mut output, &TranscribeOptions::default(), &RenderOptions::default(), &rpc_options).unwrap();
+        String::from_utf8(output).unwrap().lines().map(|line| serde_json::from_str(line).unwrap()).collect()
+    }
+
+    fn error(message: &Value) -> (i64, Option<&str>) {
+        (message["error"]["code"].as_i64().unwrap(), message["error"]["data"]["kind"].as_str())
+    }
+
+    #[test]
+    fn run_answers_protocol_errors_and_shuts_down() {
+        let lines = round_trip(&[
+            "{not json",
+            r#"{"jsonrpc":"1.0","id":1,"method":"shutdown"}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"translate"}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"transcribe","params":{"path":"a.wav","format":"docx"}}"#,
+            r#"{"jsonrpc":"2.0","id":4,"method":"cancel","params":{"id":99}}"#,
+            "",
+            r#"{"jsonrpc":"2.0","id":5,"method":"shutdown"}"#,
+            r#"{"jsonrpc":"2.0","id":6,"method":"cancel","params":{"id":99}}"#,
+        ]);
+        assert_eq!(lines.len(), 7, "{lines:?}");
+        assert_eq!(lines[0]["method"], "ready");
+        assert_eq!((lines[1]["id"].clone(), error(&lines[1])), (Value::Null, (PARSE_ERROR, None)));
+        assert_eq!((lines[2]["id"].as_i64(), error(&lines[2])), (Some(1), (INVALID_REQUEST, None)));
+        assert_eq!((lines[3]["id"].as_i64(), error(&lines[3])), (Some(2), (METHOD_NOT_FOUND, None)));
+        assert_eq!((lines[4]["id"].as_i64(), error(&lines[4])), (Some(3), (INVALID_PARAMS, None)));
+        assert_eq!(lines[5], json!({ "jsonrpc": "2.0", "id": 4, "result": { "cancelled": false } }));
+        // Nothing after `shutdown` is read.
+        assert_eq!(lines[6], json!({ "jsonrpc": "2.0", "id": 5, "result": null }));
+    }
+
+    #[test]
+    fn run_rejects_invalid_options_before_loading_the_model() {
+        let lines = round_trip(&[
+            r#"{"jsonrpc":"2.0","id":1,"method":"transcribe","params":{"path":"a.wav","language":"\u0000"}}"#,
+            r#"{"jsonrpc":"2.0","id":2,"method":"transcribe","params":{"path":"a.wav","prompt":"a\u0000b"}}"#,
+            r#"{"jsonrpc":"2.0","id":3,"method":"transcribe","params":{"path":"a.wav"}}"#,
+        ]);
+        assert_eq!(lines.len(), 4, "{lines:?}");
+        assert_eq!(error(&lines[1]), (FAILED, Some("invalid_input")));
+        assert_eq!(error(&lines[2]), (FAILED, Some("invalid_input")));
+        assert_eq!((lines[3]["id"].as_i64(), error(&lines[3])), (Some(3), (FAILED, Some("model_load"))));
+    }
+}
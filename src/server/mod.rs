@@ -0,0 +1,268 @@
+//! A local HTTP server speaking OpenAI's audio transcription API.
+//!
+//! The model is loaded once; each worker thread owns a whisper.cpp state and
+//! takes requests off the shared listener, so up to `workers` uploads are
+//! transcribed at once. Existing OpenAI clients work by pointing their base
+//! URL at the server:
+//!
+//! ```sh
+//! curl localhost:8080/v1/audio/transcriptions -F file=@call.wav -F response_format=srt
+//! ```
+//!
+//! Live audio is transcribed over a WebSocket on `/v1/audio/stream`; see
+//! [`websocket`](self::websocket) for the protocol. Stream sessions run on
+//! threads of their own, so they never hold up the upload workers.
+
+mod multipart;
+mod openai;
+mod websocket;
+
+use std::fs;
+use std::io::Read;
+use std::path::PathBuf;
+use std::process;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::thread;
+use std::time::Instant;
+
+use serde_json::json;
+use tiny_http::{Header, Method, Request, Response, Server};
+use whisper_rs::WhisperState;
+
+use crate::batch;
+use crate::control::RunControl;
+use crate::error::{Result, SttError};
+use crate::output::RenderOptions;
+use crate::stream::StreamOptions;
+use crate::transcriber::{Task, TranscribeOptions, Transcriber};
+
+use openai::AudioRequest;
+
+/// Listener settings for [`serve`].
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ServerOptions {
+    /// Address to listen on, e.g. `"127.0.0.1:8080"`.
+    pub address: String,
+    /// Requests transcribed concurrently, each on its own decoder state.
+    pub workers: usize,
+    /// Largest accepted request body, in bytes.
+    pub max_upload_bytes: usize,
+    /// WebSocket stream sessions allowed at once; each holds a decoder state.
+    pub max_streams: usize,
+    /// Window and cadence of stream sessions.
+    pub stream: StreamOptions,
+}
+
+impl Default for ServerOptions {
+    fn default() -> Self {
+        Self {
+            address: "127.0.0.1:8080".to_string(),
+            workers: 1,
+            max_upload_bytes: 25 * 1024 * 1024,
+            max_streams: 4,
+            stream: StreamOptions::default(),
+        }
+    }
+}
+
+/// An error reported to the client in OpenAI's `{"error": {...}}` shape.
+pub(crate) struct ApiError {
+    status: u16,
+    message: String,
+    kind: &'static str,
+    param: Option<&'static str>,
+}
+
+impl ApiError {
+    pub(crate) fn invalid(message: impl Into<String>, param: &'static str) -> Self {
+        ApiError { status: 400, message: message.into(), kind: "invalid_request_error", param: Some(param) }
+    }
+
+    fn new(status: u16, message: impl Into<String>, kind: &'static str) -> Self {
+        ApiError { status, message: message.into(), kind, param: None }
+    }
+}
+
+impl From<SttError> for ApiError {
+    fn from(e: SttError) -> Self {
+        match e {
+            SttError::InvalidInput(_) | SttError::AudioDecode { .. } | SttError::UnsupportedFormat(_) | SttError::Repair(_) => {
+                ApiError::new(400, e.to_string(), "invalid_request_error")
+            }
+            SttError::Io(_) | SttError::ModelLoad { .. } | SttError::Inference(_) | SttError::Cancelled => {
+                ApiError::new(500, e.to_string(), "server_error")
+            }
+        }
+    }
+}
+
+/// Serves transcription requests until the process exits.
+///
+/// Every request starts from `options`; the form fields `language`, `prompt`
+/// and `temperature` override it. When `options.threads` is `None` the
+/// physical cores are split between the workers.
+pub fn serve(transcriber: &Transcriber, options: &TranscribeOptions, render_options: &RenderOptions, server_options: &ServerOptions) -> Result<()> {
+    let workers = server_options.workers.max(1);
+    let options = TranscribeOptions { threads: Some(options.threads.unwrap_or_else(|| batch::split_threads(workers))), ..options.clone() };
+    let states = (0..workers)
+        .map(|_| transcriber.context().create_state())
+        .collect::<Result<Vec<_>, _>>()?;
+    let server = Server::http(&server_options.address).map_err(|e| SttError::Io(std::io::Error::other(e)))?;
+    eprintln!("Listening on http://{} with {} worker(s)", server_options.address, workers);
+
+    let context = Context {
+        transcriber,
+        options: &options,
+        render_options,
+        max_upload_bytes: server_options.max_upload_bytes,
+        stream_options: server_options.stream,
+        max_streams: server_options.max_streams,
+        streams: AtomicUsize::new(0),
+    };
+    let (server, context) = (&server, &context);
+    thread::scope(|scope| {
+        for mut state in states {
+            scope.spawn(move || {
+                for request in server.incoming_requests() {
+                    if request.url().split('?').next() == Some(websocket::PATH) {
+                        open_stream(context, scope, request);
+                    } else {
+                        handle(context, &mut state, request);
+                    }
+                }
+            });
+        }
+    });
+    Ok(())
+}
+
+/// What every worker shares.
+struct Context<'a> {
+    transcriber: &'a Transcriber,
+    options: &'a TranscribeOptions,
+    render_options: &'a RenderOptions,
+    max_upload_bytes: usize,
+    stream_options: StreamOptions,
+    max_streams: usize,
+    /// Stream sessions currently open.
+    streams: AtomicUsize,
+}
+
+/// Upgrades a `/v1/audio/stream` request and runs the session on a thread of its own.
+fn open_stream<'scope>(context: &'scope Context<'scope>, scope: &'scope thread::Scope<'scope, '_>, request: Request) {
+    let reject = |request: Request, error: ApiError| {
+        eprintln!("GET {} -> {}", websocket::PATH, error.status);
+        respond(request, Err(error));
+    };
+    if context.streams.fetch_add(1, Ordering::SeqCst) >= context.max_streams {
+        context.streams.fetch_sub(1, Ordering::SeqCst);
+        let error = ApiError::new(503, format!("all {} stream sessions are in use", context.max_streams), "server_error");
+        return reject(request, error);
+    }
+    match websocket::accept_key(&request) {
+        Ok(accept_key) => {
+            eprintln!("GET {} -> 101", websocket::PATH);
+            let socket = websocket::upgrade(request, &accept_key);
+            scope.spawn(move || {
+                let start = Instant::now();
+                websocket::run(context, socket);
+                context.streams.fetch_sub(1, Ordering::SeqCst);
+                eprintln!("Stream session closed after {:.2?}", start.elapsed());
+            });
+        }
+        Err(error) => {
+            context.streams.fetch_sub(1, Ordering::SeqCst);
+            reject(request, error);
+        }
+    }
+}
+
+fn handle(context: &Context<'_>, state: &mut WhisperState, mut request: Request) {
+    let start = Instant::now();
+    let method = request.method().clone();
+    let path = request.url().split('?').next().unwrap_or_default().to_string();
+    let result = match (&method, path.as_str()) {
+        (Method::Post, "/v1/audio/transcriptions") => transcribe(context, state, &mut request, Task::Transcribe),
+        (Method::Post, "/v1/audio/translations") => transcribe(context, state, &mut request, Task::Translate),
+        (_, "/v1/audio/transcriptions" | "/v1/audio/translations") => {
+            Err(ApiError::new(405, format!("{} is not allowed here; use POST", method), "invalid_request_error"))
+        }
+        _ => Err(ApiError::new(404, format!("no route for {} {}", method, path), "invalid_request_error")),
+    };
+
+    let status = result.as_ref().map_or_else(|e| e.status, |_| 200);
+    eprintln!("{} {} -> {} ({:.2?})", method, path, status, start.elapsed());
+    respond(request, result);
+}
+
+fn respond(request: Request, result: Result<(&'static str, String), ApiError>) {
+    let (status, content_type, body) = match result {
+        Ok((content_type, body)) => (200, content_type, body),
+        Err(e) => {
+            let body = json!({ "error": { "message": e.message, "type": e.kind, "param": e.param, "code": null } });
+            (e.status, "application/json", body.to_string())
+        }
+    };
+    let header = Header::from_bytes("Content-Type", content_type).expect("content types are valid header values");
+    let response = Response::from_string(body).with_status_code(status).with_header(header);
+    if let Err(e) = request.respond(response) {
+        eprintln!("Failed to send response: {}", e);
+    }
+}
+
+/// The value of the request header `name`.
+fn header(request: &Request, name: &'static str) -> Option<String> {
+    request.headers().iter().find(|h| h.field.equiv(name)).map(|h| h.value.as_str().to_string())
+}
+
+fn transcribe(context: &Context<'_>, state: &mut WhisperState, request: &mut Request, task: Task) -> Result<(&'static str, String), ApiError> {
+    let content_type = header(request, "Content-Type").unwrap_or_default();
+    let boundary = multipart::boundary(&content_type)
+        .ok_or_else(|| ApiError::new(400, "expected a multipart/form-data upload", "invalid_request_error"))?;
+    let too_large = || ApiError::new(413, format!("uploads are limited to {} bytes", context.max_upload_bytes), "invalid_request_error");
+    if request.body_length().is_some_and(|length| length > context.max_upload_bytes) {
+        return Err(too_large());
+    }
+    let mut body = Vec::new();
+    request.as_reader().take(context.max_upload_bytes as u64 + 1).read_to_end(&mut body).map_err(SttError::from)?;
+    if body.len() > context.max_upload_bytes {
+        return Err(too_large());
+    }
+
+    let parts = multipart::parse(&body, &boundary).map_err(|e| ApiError::new(400, e, "invalid_request_error"))?;
+    let upload = AudioRequest::from_parts(parts)?;
+    let options = upload.options(context.options, task);
+    context.transcriber.check_options(&options)?;
+
+    // The upload is staged in a scratch file and transcribed like any other
+    // file, so WAV is streamed and every channel mode and fallback applies.
+    let scratch = ScratchFile::write(&upload.filename, &upload.file)?;
+    let control = RunControl::default();
+    let transcript = context.transcriber.transcribe_file_with_control(state, &scratch.0, &options, &control)?;
+    let duration_ms = control.total_ms().unwrap_or_else(|| transcript.segments.last().map_or(0, |s| s.end_ms));
+    Ok(openai::render(&transcript, duration_ms as f64 / 1000.0, upload.response_format, context.render_options))
+}
+
+/// An uploaded file on disk, deleted when dropped.
+struct ScratchFile(PathBuf);
+
+impl ScratchFile {
+    fn write(filename: &str, data: &[u8]) -> Result<Self> {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+        let extension = filename.rsplit_once('.').map(|(_, ext)| ext).filter(|ext| ext.chars().all(char::is_alphanumeric)).unwrap_or("bin");
+        let path = std::env::temp_dir().join(format!(
+            "ruststt-upload-{}-{}.{}",
+            process::id(),
+            COUNTER.fetch_add(1, Ordering::Relaxed),
+            extension
+        ));
+        fs::write(&path, data)?;
+        Ok(ScratchFile(path))
+    }
+}
+
+impl Drop for ScratchFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
@@ -0,0 +1,116 @@
+//! Just enough `multipart/form-data` parsing for audio uploads.
+
+/// One field of a form upload.
+pub(crate) struct Part {
+    pub(crate) name: String,
+    /// Set for file fields.
+    pub(crate) filename: Option<String>,
+    pub(crate) data: Vec<u8>,
+}
+
+impl Part {
+    /// The field value as text, for non-file fields.
+    pub(crate) fn text(&self) -> String {
+        String::from_utf8_lossy(&self.data).trim().to_string()
+    }
+}
+
+/// The `boundary` parameter of a `multipart/form-data` content type.
+pub(crate) fn boundary(content_type: &str) -> Option<String> {
+    let mut params = content_type.split(';').map(str::trim);
+    if !params.next()?.eq_ignore_ascii_case("multipart/form-data") {
+        return None;
+    }
+    params
+        .filter_map(|param| param.split_once('='))
+        .find(|(key, _)| key.trim().eq_ignore_ascii_case("boundary"))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+        .filter(|value| !value.is_empty())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// The value of `key="..."` in a `Content-Disposition` header.
+fn disposition_param(header: &str, key: &str) -> Option<String> {
+    header
+        .split(';')
+        .map(str::trim)
+        .filter_map(|param| param.split_once('='))
+        .find(|(k, _)| k.trim().eq_ignore_ascii_case(key))
+        .map(|(_, value)| value.trim().trim_matches('"').to_string())
+}
+
+/// Splits a form body into its parts.
+pub(crate) fn parse(body: &[u8], boundary: &str) -> Result<Vec<Part>, String> {
+    let delimiter = format!("--{}", boundary).into_bytes();
+    let separator = format!("\r\n--{}", boundary).into_bytes();
+    let mut position = find(body, &delimiter).ok_or("multipart body has no opening boundary")? + delimiter.len();
+    let mut parts = Vec::new();
+
+    loop {
+        if body[position..].starts_with(b"--") {
+            return Ok(parts);
+        }
+        if !body[position..].starts_with(b"\r\n") {
+            return Err("malformed multipart boundary line".to_string());
+        }
+        let headers_start = position + 2;
+        let headers_len = find(&body[headers_start..], b"\r\n\r\n").ok_or("multipart part has no header terminator")?;
+        let headers = String::from_utf8_lossy(&body[headers_start..headers_start + headers_len]);
+        let data_start = headers_start + headers_len + 4;
+        let data_len = find(&body[data_start..], &separator).ok_or("multipart body is truncated")?;
+
+        let disposition = headers
+            .lines()
+            .filter_map(|line| line.split_once(':'))
+            .find(|(name, _)| name.trim().eq_ignore_ascii_case("content-disposition"))
+            .map(|(_, value)| value.to_string())
+            .ok_or("multipart part has no Content-Disposition header")?;
+        let name = disposition_param(&disposition, "name").ok_or("multipart part has no field name")?;
+        parts.push(Part {
+            name,
+            filename: disposition_param(&disposition, "filename"),
+            data: body[data_start..data_start + data_len].to_vec(),
+        });
+        position = data_start + data_len + separator.len();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn boundary_is_read_from_the_content_type() {
+        assert_eq!(boundary("multipart/form-data; boundary=\"abc 123\"").as_deref(), Some("abc 123"));
+        assert_eq!(boundary("Multipart/Form-Data;charset=utf-8;BOUNDARY=xyz").as_deref(), Some("xyz"));
+        assert_eq!(boundary("application/json; boundary=xyz"), None);
+        assert_eq!(boundary("multipart/form-data; boundary="), None);
+    }
+
+    #[test]
+    fn parse_splits_file_and_text_fields() {
+        let body = b"preamble\r\n--XX\r\n\
+            Content-Disposition: form-data; name=\"file\"; filename=\"a.wav\"\r\n\
+            Content-Type: audio/wav\r\n\r\n\
+            RIFF\r\n--X\0\xff\r\n--XX\r\n\
+            content-disposition: form-data; name=\"language\"\r\n\r\n\
+            de \r\n--XX--\r\n";
+        let parts = parse(body, "XX").unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!((parts[0].name.as_str(), parts[0].filename.as_deref()), ("file", Some("a.wav")));
+        assert_eq!(parts[0].data, b"RIFF\r\n--X\0\xff");
+        assert_eq!((parts[1].name.as_str(), parts[1].filename.as_deref()), ("language", None));
+        assert_eq!(parts[1].text(), "de");
+    }
+
+    #[test]
+    fn parse_rejects_malformed_bodies() {
+        assert!(parse(b"no boundary here", "XX").is_err());
+        assert!(parse(b"--XX\r\nContent-Disposition: form-data; name=\"a\"\r\n\r\nunterminated", "XX").is_err());
+        assert!(parse(b"--XX\r\nContent-Type: text/plain\r\n\r\nx\r\n--XX--", "XX").is_err());
+        assert!(parse(b"--XXjunk", "XX").is_err());
+    }
+}
@@ -0,0 +1,206 @@
+//! Request fields and response bodies of OpenAI's audio API.
+
+use serde_json::{json, Value};
+
+use crate::output::{self, RenderOptions};
+use crate::transcriber::{Task, TranscribeOptions, Transcript};
+
+use super::ApiError;
+use super::multipart::Part;
+
+/// The `response_format` field.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub(crate) enum ResponseFormat {
+    /// `{"text": "..."}`
+    #[default]
+    Json,
+    Text,
+    Srt,
+    Vtt,
+    /// Language, duration and timed segments (and words, when requested).
+    VerboseJson,
+}
+
+impl std::str::FromStr for ResponseFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(ResponseFormat::Json),
+            "text" => Ok(ResponseFormat::Text),
+            "srt" => Ok(ResponseFormat::Srt),
+            "vtt" => Ok(ResponseFormat::Vtt),
+            "verbose_json" => Ok(ResponseFormat::VerboseJson),
+            other => Err(format!("unknown response_format '{}' (expected json, text, srt, vtt or verbose_json)", other)),
+        }
+    }
+}
+
+/// The fields of a `/v1/audio/transcriptions` or `/v1/audio/translations` upload.
+pub(crate) struct AudioRequest {
+    pub(crate) file: Vec<u8>,
+    pub(crate) filename: String,
+    language: Option<String>,
+    prompt: Option<String>,
+    pub(crate) response_format: ResponseFormat,
+    temperature: Option<f32>,
+    /// `timestamp_granularities[]` included `word`.
+    words: bool,
+}
+
+impl AudioRequest {
+    /// Collects the known fields; `model` and unknown fields are ignored,
+    /// since the server always uses the model it was started with.
+    pub(crate) fn from_parts(parts: Vec<Part>) -> Result<Self, ApiError> {
+        let mut file = None;
+        let mut request = AudioRequest {
+            file: Vec::new(),
+            filename: String::new(),
+            language: None,
+            prompt: None,
+            response_format: ResponseFormat::default(),
+            temperature: None,
+            words: false,
+        };
+        for part in parts {
+            match part.name.as_str() {
+                "file" => file = Some((part.filename.clone().unwrap_or_default(), part.data)),
+                "language" => request.language = Some(part.text()).filter(|l| !l.is_empty()),
+                "prompt" => request.prompt = Some(part.text()).filter(|p| !p.is_empty()),
+                "response_format" => {
+                    request.response_format = part.text().parse().map_err(|e| ApiError::invalid(e, "response_format"))?;
+                }
+                "temperature" => {
+                    let temperature: f32 = part.text().parse()
+                        .map_err(|_| ApiError::invalid("temperature must be a number", "temperature"))?;
+                    if !(0.0..=1.0).contains(&temperature) {
+                        return Err(ApiError::invalid("temperature must be between 0 and 1", "temperature"));
+                    }
+                    request.temperature = Some(temperature);
+                }
+                "timestamp_granularities[]" | "timestamp_granularities" => {
+                    request.words |= part.text() == "word";
+                }
+                _ => {}
+            }
+        }
+        let (filename, data) = file.ok_or_else(|| ApiError::invalid("the 'file' field is required", "file"))?;
+        if data.is_empty() {
+            return Err(ApiError::invalid("the uploaded file is empty", "file"));
+        }
+        request.file = data;
+        request.filename = filename;
+        Ok(request)
+    }
+
+    /// `base` with this request's overrides applied.
+    ///
+    /// Translations default to detecting the spoken language, as OpenAI's
+    /// endpoint takes no `language` field.
+    pub(crate) fn options(&self, base: &TranscribeOptions, task: Task) -> TranscribeOptions {
+        let mut options = TranscribeOptions { task, ..base.clone() };
+        match &self.language {
+            Some(language) => options.language = language.clone(),
+            None if task == Task::Translate => options.language = "auto".to_string(),
+            None => {}
+        }
+        if let Some(prompt) = &self.prompt {
+            options.initial_prompt = Some(prompt.clone());
+        }
+        if let Some(temperature) = self.temperature {
+            options.decoding.temperature = temperature;
+        }
+        options.word_timestamps |= self.words;
+        options
+    }
+}
+
+/// The response body and its content type.
+pub(crate) fn render(transcript: &Transcript, duration_s: f64, format: ResponseFormat, render_options: &RenderOptions) -> (&'static str, String) {
+    match format {
+        ResponseFormat::Json => ("application/json", json!({ "text": transcript.text() }).to_string()),
+        ResponseFormat::Text => ("text/plain; charset=utf-8", format!("{}\n", transcript.text())),
+        ResponseFormat::Srt => ("application/x-subrip", output::render_srt(transcript, &render_options.subtitles)),
+        ResponseFormat::Vtt => ("text/vtt", output::render_vtt(transcript, &render_options.subtitles)),
+        ResponseFormat::VerboseJson => ("application/json", verbose_json(transcript, duration_s).to_string()),
+    }
+}
+
+/// OpenAI's `verbose_json` body. Token ids and compression ratios are not
+/// tracked and are left out of the segments.
+fn verbose_json(transcript: &Transcript, duration_s: f64) -> Value {
+    let options = &transcript.run.options;
+    let code = transcript.run.detected_language.as_ref().map_or(options.language.as_str(), |d| d.language.as_str());
+    let language = whisper_rs::get_lang_id(code).and_then(whisper_rs::get_lang_str_full).unwrap_or(code);
+    let seconds = |ms: i64| ms as f64 / 1000.0;
+
+    let segments: Vec<Value> = transcript.segments.iter().enumerate().map(|(id, segment)| {
+        let mut value = json!({
+            "id": id,
+            "seek": 0,
+            "start": seconds(segment.start_ms),
+            "end": seconds(segment.end_ms),
+            "text": segment.text,
+            "temperature": options.decoding.temperature,
+            "avg_logprob": segment.avg_logprob,
+            "no_speech_prob": segment.no_speech_prob,
+        });
+        if let Some(speaker) = &segment.speaker {
+            value["speaker"] = json!(speaker);
+        }
+        value
+    }).collect();
+
+    let mut body = json!({
+        "task": options.task,
+        "language": language,
+        "duration": duration_s,
+        "text": transcript.text(),
+        "segments": segments,
+    });
+    if options.word_timestamps {
+        body["words"] = transcript.segments.iter()
+            .flat_map(|segment| &segment.words)
+            .map(|word| json!({ "word": word.text, "start": seconds(word.start_ms), "end": seconds(word.end_ms) }))
+            .collect();
+    }
+    body
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn part(name: &str, data: &[u8]) -> Part {
+        let filename = (name == "file").then(|| "a.wav".to_string());
+        Part { name: name.to_string(), filename, data: data.to_vec() }
+    }
+
+    #[test]
+    fn from_parts_applies_fields_over_the_server_defaults() {
+        let parts = vec![part("file", b"RIFF"), part("language", b" de "), part("prompt", b"Hallo."), part("response_format", b"srt"), part("temperature", b"0.4")];
+        let request = AudioRequest::from_parts(parts).ok().unwrap();
+        assert_eq!((request.filename.as_str(), request.response_format), ("a.wav", ResponseFormat::Srt));
+        let options = request.options(&TranscribeOptions::default(), Task::Transcribe);
+        assert_eq!((options.language.as_str(), options.initial_prompt.as_deref()), ("de", Some("Hallo.")));
+        assert_eq!(options.decoding.temperature, 0.4);
+    }
+
+    #[test]
+    fn nul_in_language_or_prompt_is_a_bad_request() {
+        for field in ["language", "prompt"] {
+            let request = AudioRequest::from_parts(vec![part("file", b"RIFF"), part(field, b"e\0n")]).ok().unwrap();
+            let error = ApiError::from(request.options(&TranscribeOptions::default(), Task::Transcribe).validate().unwrap_err());
+            assert_eq!(error.status, 400, "{field}");
+        }
+    }
+
+    #[test]
+    fn from_parts_rejects_missing_files_and_bad_fields() {
+        let status = |parts| AudioRequest::from_parts(parts).err().map(|e| (e.status, e.param));
+        assert_eq!(status(vec![part("language", b"en")]), Some((400, Some("file"))));
+        assert_eq!(status(vec![part("file", b"")]), Some((400, Some("file"))));
+        assert_eq!(status(vec![part("file", b"RIFF"), part("temperature", b"2")]), Some((400, Some("temperature"))));
+        assert_eq!(status(vec![part("file", b"RIFF"), part("response_format", b"xml")]), Some((400, Some("response_format"))));
+    }
+}
@@ -0,0 +1,164 @@
+//! Live transcription over a WebSocket.
+//!
+//! The client opens `GET /v1/audio/stream`, describes its audio in a text
+//! message, then sends raw PCM in binary messages:
+//!
+//! ```text
+//! -> {"type":"start","sample_rate":48000,"channels":2,"format":"f32le","language":"en"}
+//! <- {"type":"ready"}
+//! -> <binary PCM> ...
+//! <- {"type":"partial","start_ms":0,"end_ms":1800,"text":"Hello th",...}
+//! <- {"type":"final","start_ms":0,"end_ms":2100,"text":"Hello there.",...}
+//! -> {"type":"end"}
+//! <- ...remaining finals, then {"type":"done"}
+//! ```
+//!
+//! Each connection runs a [`StreamTranscriber`] with its own decoder state on
+//! its own thread. Problems are reported as `{"type":"error","message":...}`
+//! before the socket is closed.
+
+use serde::Deserialize;
+use serde_json::json;
+use tiny_http::{Header, Request, Response, ReadWrite, StatusCode};
+use tungstenite::protocol::Role;
+use tungstenite::{Message, WebSocket};
+
+use crate::audio::ChannelMode;
+use crate::resample::WHISPER_SAMPLE_RATE;
+use crate::stream::{PcmConverter, PcmFormat, StreamEvent, StreamTranscriber};
+use crate::transcriber::TranscribeOptions;
+
+use super::{ApiError, Context};
+
+/// Route of the streaming endpoint.
+pub(crate) const PATH: &str = "/v1/audio/stream";
+
+/// Text messages a client may send.
+#[derive(Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+enum ClientMessage {
+    /// Describes the PCM that follows; must come first.
+    Start {
+        #[serde(default = "default_rate")]
+        sample_rate: u32,
+        #[serde(default = "default_channels")]
+        channels: u16,
+        /// `s16le` or `f32le`.
+        #[serde(default = "default_format")]
+        format: String,
+        /// Overrides the server's language, e.g. `"auto"`.
+        language: Option<String>,
+    },
+    /// No more audio: flush the pending hypothesis as final segments.
+    End,
+}
+
+fn default_rate() -> u32 {
+    WHISPER_SAMPLE_RATE
+}
+
+fn default_channels() -> u16 {
+    1
+}
+
+fn default_format() -> String {
+    "s16le".to_string()
+}
+
+/// The `Sec-WebSocket-Accept` answer to a WebSocket upgrade request.
+pub(crate) fn accept_key(request: &Request) -> Result<String, ApiError> {
+    let is_websocket = super::header(request, "Upgrade").is_some_and(|u| u.eq_ignore_ascii_case("websocket"));
+    let key = super::header(request, "Sec-WebSocket-Key")
+        .filter(|_| is_websocket)
+        .ok_or_else(|| ApiError::new(426, format!("{} expects a WebSocket upgrade", PATH), "invalid_request_error"))?;
+    Ok(tungstenite::handshake::derive_accept_key(key.trim().as_bytes()))
+}
+
+/// Completes the opening handshake and hands back the raw connection.
+pub(crate) fn upgrade(request: Request, accept_key: &str) -> Box<dyn ReadWrite + Send> {
+    let header = |name: &str, value: &str| Header::from_bytes(name, value).expect("handshake headers are valid");
+    let response = Response::empty(StatusCode(101))
+        .with_header(header("Upgrade", "websocket"))
+        .with_header(header("Connection", "Upgrade"))
+        .with_header(header("Sec-WebSocket-Accept", accept_key));
+    request.upgrade("websocket", response)
+}
+
+/// Runs one streaming session until the client ends it or disconnects.
+pub(crate) fn run(context: &Context<'_>, socket: Box<dyn ReadWrite + Send>) {
+    let mut socket = WebSocket::from_raw_socket(socket, Role::Server, None);
+    if let Err(message) = session(context, &mut socket) {
+        eprintln!("Stream session failed: {}", message);
+        let _ = socket.send(Message::text(json!({ "type": "error", "message": message }).to_string()));
+    }
+    let _ = socket.close(None);
+    let _ = socket.flush();
+}
+
+fn send_events(socket: &mut WebSocket<Box<dyn ReadWrite + Send>>, events: &[StreamEvent]) -> Result<(), String> {
+    for event in events {
+        let text = serde_json::to_string(event).expect("stream events serialize to JSON");
+        socket.send(Message::text(text)).map_err(|e| e.to_string())?;
+    }
+    Ok(())
+}
+
+fn parse(text: &str) -> Result<ClientMessage, String> {
+    serde_json::from_str(text).map_err(|e| format!("invalid message: {}", e))
+}
+
+fn session(context: &Context<'_>, socket: &mut WebSocket<Box<dyn ReadWrite + Send>>) -> Result<(), String> {
+    let (format, language) = loop {
+        match socket.read() {
+            Ok(Message::Text(text)) => match parse(&text)? {
+                ClientMessage::Start { sample_rate, channels, format, language } => {
+                    let encoding = format.parse()?;
+                    break (PcmFormat { sample_rate, channels, encoding }, language);
+                }
+                ClientMessage::End => return Ok(()),
+            },
+            Ok(Message::Binary(_)) => return Err("send a start message before any audio".to_string()),
+            Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Ok(_) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    };
+
+    let mut options: TranscribeOptions = context.options.clone();
+    if let Some(language) = language {
+        options.language = language;
+    }
+    context.transcriber.check_options(&options).map_err(|e| e.to_string())?;
+    // Streams are always one signal; separate-channel mode only applies to files.
+    let channel = match options.audio.channels {
+        ChannelMode::Separate => ChannelMode::Downmix,
+        mode => mode,
+    };
+    let mut converter = PcmConverter::new(format, channel, options.audio.resample_quality).map_err(|e| e.to_string())?;
+    let mut transcriber = StreamTranscriber::new(context.transcriber, options, context.stream_options).map_err(|e| e.to_string())?;
+    socket.send(Message::text(json!({ "type": "ready" }).to_string())).map_err(|e| e.to_string())?;
+
+    loop {
+        match socket.read() {
+            Ok(Message::Binary(bytes)) => {
+                let samples = converter.push(&bytes);
+                let events = transcriber.push(&samples).map_err(|e| e.to_string())?;
+                send_events(socket, &events)?;
+            }
+            Ok(Message::Text(text)) => match parse(&text)? {
+                ClientMessage::End => break,
+                ClientMessage::Start { .. } => return Err("the stream has already started".to_string()),
+            },
+            Ok(Message::Close(_)) | Err(tungstenite::Error::ConnectionClosed) => return Ok(()),
+            Ok(_) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+
+    let tail = converter.flush();
+    let events = transcriber.push(&tail).map_err(|e| e.to_string())?;
+    send_events(socket, &events)?;
+    let events = transcriber.finish().map_err(|e| e.to_string())?;
+    send_events(socket, &events)?;
+    socket.send(Message::text(json!({ "type": "done" }).to_string())).map_err(|e| e.to_string())
+}
@@ -0,0 +1,355 @@
+//! Live transcription of audio that arrives in chunks.
+//!
+//! [`StreamTranscriber`] keeps a rolling window of not-yet-final audio and
+//! re-decodes it every `step_ms` of new input, the way whisper.cpp's `stream`
+//! example does. A segment becomes final once two consecutive decodes agree on
+//! it and a later segment has started after it; its audio then leaves the
+//! window. Everything after the last final segment is reported as a partial
+//! hypothesis that may still change.
+
+use serde::Serialize;
+use whisper_rs::WhisperState;
+
+use crate::audio::ChannelMode;
+use crate::control::RunControl;
+use crate::error::{Result, SttError};
+use crate::prompt;
+use crate::resample::{ResampleQuality, StreamResampler, WHISPER_SAMPLE_RATE};
+use crate::transcriber::{Segment, TranscribeOptions, Transcriber};
+use crate::vad::SpeechDetector;
+
+/// Window and cadence of the streaming decoder.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct StreamOptions {
+    /// Decode again after this much new audio.
+    pub step_ms: u32,
+    /// Longest stretch of audio decoded at once; when reached, pending
+    /// segments are finalised even without agreement.
+    pub window_ms: u32,
+    /// Audio kept before the first pending segment as acoustic context.
+    pub keep_ms: u32,
+}
+
+impl Default for StreamOptions {
+    fn default() -> Self {
+        Self { step_ms: 2000, window_ms: 15_000, keep_ms: 200 }
+    }
+}
+
+/// Hypotheses emitted while streaming.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StreamEvent {
+    /// Best guess for the audio after the last final segment; superseded by
+    /// the next partial or final event.
+    Partial(Segment),
+    /// A segment that will not change any more.
+    Final(Segment),
+}
+
+const SAMPLES_PER_MS: u64 = WHISPER_SAMPLE_RATE as u64 / 1000;
+
+/// Input rates [`PcmConverter`] accepts. The resampler's filter table grows
+/// with the rate ratio, so arbitrary client-supplied rates are refused.
+pub const PCM_SAMPLE_RATES: std::ops::RangeInclusive<u32> = 8_000..=192_000;
+
+/// Most interleaved channels [`PcmConverter`] accepts.
+pub const MAX_PCM_CHANNELS: u16 = 32;
+
+/// A streaming session over one loaded model.
+pub struct StreamTranscriber<'a> {
+    transcriber: &'a Transcriber,
+    state: WhisperState,
+    detector: SpeechDetector,
+    options: TranscribeOptions,
+    stream: StreamOptions,
+    /// Pending 16 kHz audio starting at absolute sample `window_start`.
+    window: Vec<f32>,
+    window_start: u64,
+    since_last_decode: usize,
+    /// Pending segments from the previous decode, for the agreement check.
+    previous: Vec<Segment>,
+    /// Recently finalised text, carried as prompt context when enabled.
+    context: String,
+}
+
+impl<'a> StreamTranscriber<'a> {
+    pub fn new(transcriber: &'a Transcriber, options: TranscribeOptions, stream: StreamOptions) -> Result<Self> {
+        Ok(Self {
+            state: transcriber.context().create_state()?,
+            detector: SpeechDetector::new(&options.vad, Some(options.thread_count()))?,
+            transcriber,
+            options,
+            stream,
+            window: Vec::new(),
+            window_start: 0,
+            since_last_decode: 0,
+            previous: Vec::new(),
+            context: String::new(),
+        })
+    }
+
+    /// Appends 16 kHz mono samples and decodes if a step's worth has accumulated.
+    pub fn push(&mut self, samples: &[f32]) -> Result<Vec<StreamEvent>> {
+        self.window.extend_from_slice(samples);
+        self.since_last_decode += samples.len();
+        if self.since_last_decode < self.stream.step_ms as usize * SAMPLES_PER_MS as usize {
+            return Ok(Vec::new());
+        }
+        self.decode(false)
+    }
+
+    /// Decodes whatever is pending and finalises it.
+    pub fn finish(&mut self) -> Result<Vec<StreamEvent>> {
+        self.decode(true)
+    }
+
+    fn decode(&mut self, end_of_stream: bool) -> Result<Vec<StreamEvent>> {
+        self.since_last_decode = 0;
+        if self.window.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let offset_ms = (self.window_start / SAMPLES_PER_MS) as i64;
+        let context = (!self.context.is_empty()).then_some(self.context.as_str());
+        let transcript = self.transcriber.transcribe_with_context(&mut self.state, &self.window, &self.options, &mut self.detector, context, &RunControl::default())?;
+        // Keep the first detected language rather than re-detecting every step.
+        if let Some(detected) = transcript.run.detected_language {
+            self.options.language = detected.language;
+        }
+        let mut segments: Vec<Segment> = transcript.segments.into_iter().filter(|s| !s.text.is_empty()).collect();
+        for segment in &mut segments {
+            segment.offset_by(offset_ms);
+        }
+
+        let window_full = self.window.len() as u64 >= self.stream.window_ms as u64 * SAMPLES_PER_MS;
+        let stable = if end_of_stream || (window_full && segments.len() <= 1) {
+            segments.len()
+        } else if window_full {
+            segments.len() - 1
+        } else {
+            // The last segment may still be growing, so it never counts as agreed.
+            segments
+                .iter()
+                .zip(&self.previous)
+                .take_while(|(now, before)| now.text == before.text)
+                .count()
+                .min(segments.len().saturating_sub(1))
+        };
+
+        let mut events: Vec<StreamEvent> = segments.drain(..stable).map(StreamEvent::Final).collect();
+        if self.options.carry_context {
+            for event in &events {
+                if let StreamEvent::Final(segment) = event {
+                    self.context.push(' ');
+                    self.context.push_str(&segment.text);
+                }
+            }
+            let excess = self.context.len().saturating_sub(2 * prompt::MAX_CONTEXT_CHARS);
+            if excess > 0 {
+                let cut = (excess..self.context.len()).find(|&i| self.context.is_char_boundary(i)).unwrap_or(0);
+                self.context.drain(..cut);
+            }
+        }
+        if let Some(StreamEvent::Final(last)) = events.last() {
+            let keep = self.stream.keep_ms as u64 * SAMPLES_PER_MS;
+            let cut = (last.end_ms.max(0) as u64 * SAMPLES_PER_MS).saturating_sub(keep);
+            self.trim_to(cut);
+        } else if window_full {
+            // Nothing recognisable in a full window: drop all but the context tail.
+            let keep = self.stream.keep_ms as u64 * SAMPLES_PER_MS;
+            self.trim_to((self.window_start + self.window.len() as u64).saturating_sub(keep));
+        }
+
+        if end_of_stream {
+            self.window.clear();
+            self.previous.clear();
+        } else {
+            if let Some(partial) = merge(&segments) {
+                events.push(StreamEvent::Partial(partial));
+            }
+            self.previous = segments;
+        }
+        Ok(events)
+    }
+
+    /// Drops pending audio before absolute sample `cut`.
+    fn trim_to(&mut self, cut: u64) {
+        if cut <= self.window_start {
+            return;
+        }
+        let drop = ((cut - self.window_start) as usize).min(self.window.len());
+        self.window.drain(..drop);
+        self.window_start += drop as u64;
+    }
+}
+
+/// Joins pending segments into a single partial hypothesis.
+fn merge(segments: &[Segment]) -> Option<Segment> {
+    let first = segments.first()?;
+    let last = segments.last()?;
+    Some(Segment {
+        start_ms: first.start_ms,
+        end_ms: last.end_ms,
+        text: segments.iter().map(|s| s.text.as_str()).collect::<Vec<_>>().join(" "),
+        no_speech_prob: segments.iter().map(|s| s.no_speech_prob).fold(f32::INFINITY, f32::min),
+        avg_logprob: segments.iter().map(|s| s.avg_logprob).sum::<f32>() / segments.len() as f32,
+        words: segments.iter().flat_map(|s| s.words.iter().cloned()).collect(),
+        ..Segment::default()
+    })
+}
+
+/// Binary encoding of raw PCM input.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub enum SampleEncoding {
+    /// Signed 16-bit little-endian integers.
+    #[default]
+    S16Le,
+    /// 32-bit little-endian IEEE floats.
+    F32Le,
+}
+
+impl SampleEncoding {
+    pub fn bytes_per_sample(self) -> usize {
+        match self {
+            SampleEncoding::S16Le => 2,
+            SampleEncoding::F32Le => 4,
+        }
+    }
+}
+
+impl std::str::FromStr for SampleEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "s16le" | "s16" | "i16" => Ok(SampleEncoding::S16Le),
+            "f32le" | "f32" => Ok(SampleEncoding::F32Le),
+            other => Err(format!("unknown sample encoding '{}' (expected s16le or f32le)", other)),
+        }
+    }
+}
+
+/// Layout of a raw interleaved PCM byte stream.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PcmFormat {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub encoding: SampleEncoding,
+}
+
+impl Default for PcmFormat {
+    fn default() -> Self {
+        Self { sample_rate: WHISPER_SAMPLE_RATE, channels: 1, encoding: SampleEncoding::S16Le }
+    }
+}
+
+impl PcmFormat {
+    pub fn bytes_per_frame(&self) -> usize {
+        self.encoding.bytes_per_sample() * self.channels as usize
+    }
+}
+
+/// Turns raw PCM bytes of any [`PcmFormat`] into 16 kHz mono samples,
+/// carrying partial frames and resampler state across calls.
+pub struct PcmConverter {
+    format: PcmFormat,
+    channel: ChannelMode,
+    resampler: StreamResampler,
+    leftover: Vec<u8>,
+}
+
+impl PcmConverter {
+    pub fn new(format: PcmFormat, channel: ChannelMode, quality: ResampleQuality) -> Result<Self> {
+        if !PCM_SAMPLE_RATES.contains(&format.sample_rate) {
+            return Err(SttError::InvalidInput(format!(
+                "PCM sample rate {} Hz is outside {}..={} Hz",
+                format.sample_rate, PCM_SAMPLE_RATES.start(), PCM_SAMPLE_RATES.end()
+            )));
+        }
+        if !(1..=MAX_PCM_CHANNELS).contains(&format.channels) {
+            return Err(SttError::InvalidInput(format!(
+                "PCM streams need 1 to {} channels, not {}", MAX_PCM_CHANNELS, format.channels
+            )));
+        }
+        if let ChannelMode::Select(index) = channel
+            && index >= format.channels
+        {
+            return Err(SttError::InvalidInput(format!(
+                "channel {} requested but the stream only has {} channel(s)", index, format.channels
+            )));
+        }
+        if channel == ChannelMode::Separate {
+            return Err(SttError::InvalidInput("separate channel transcription is only available for files".to_string()));
+        }
+        Ok(Self {
+            format,
+            channel,
+            resampler: StreamResampler::new(format.sample_rate, WHISPER_SAMPLE_RATE, quality),
+            leftover: Vec::new(),
+        })
+    }
+
+    /// Converts every complete frame in `bytes` (plus any carried remainder).
+    pub fn push(&mut self, bytes: &[u8]) -> Vec<f32> {
+        self.leftover.extend_from_slice(bytes);
+        let frame_bytes = self.format.bytes_per_frame();
+        let whole = self.leftover.len() / frame_bytes * frame_bytes;
+        let samples: Vec<f32> = match self.format.encoding {
+            SampleEncoding::S16Le => self.leftover[..whole]
+                .chunks_exact(2)
+                .map(|b| i16::from_le_bytes([b[0], b[1]]) as f32 / 32768.0)
+                .collect(),
+            SampleEncoding::F32Le => self.leftover[..whole]
+                .chunks_exact(4)
+                .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]))
+                .collect(),
+        };
+        self.leftover.drain(..whole);
+
+        let channels = self.format.channels as usize;
+        let mono: Vec<f32> = match self.channel {
+            _ if channels == 1 => samples,
+            ChannelMode::Select(index) => samples.iter().skip(index as usize).step_by(channels).copied().collect(),
+            ChannelMode::Downmix | ChannelMode::Separate => samples
+                .chunks_exact(channels)
+                .map(|frame| frame.iter().sum::<f32>() / channels as f32)
+                .collect(),
+        };
+        self.resampler.process(&mono)
+    }
+
+    /// Emits the resampler's held-back tail at end of input.
+    pub fn flush(&mut self) -> Vec<f32> {
+        self.leftover.clear();
+        self.resampler.flush()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn converter(sample_rate: u32, channels: u16) -> Result<PcmConverter> {
+        let format = PcmFormat { sample_rate, channels, encoding: SampleEncoding::S16Le };
+        PcmConverter::new(format, ChannelMode::Downmix, ResampleQuality::default())
+    }
+
+    #[test]
+    fn pcm_converter_rejects_rates_and_channel_counts_out_of_range() {
+        for (rate, channels) in [(0, 1), (7_999, 1), (192_001, 1), (4_294_967_291, 1), (16_000, 0), (16_000, 33)] {
+            assert!(matches!(converter(rate, channels), Err(SttError::InvalidInput(_))), "{rate} Hz, {channels} channels");
+        }
+        assert!(converter(8_000, 1).is_ok());
+        assert!(converter(192_000, 32).is_ok());
+    }
+
+    #[test]
+    fn pcm_converter_downmixes_and_carries_partial_frames() {
+        let mut converter = converter(16_000, 2).unwrap();
+        let bytes: Vec<u8> = [16_384i16, 0, -16_384, -16_384].iter().flat_map(|s| s.to_le_bytes()).collect();
+        let mut samples = converter.push(&bytes[..5]);
+        samples.extend(converter.push(&bytes[5..]));
+        assert_eq!(samples, vec![0.25, -0.5]);
+    }
+}
@@ -0,0 +1,595 @@
+//! The embeddable transcription engine.
+
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use whisper_rs::{DtwMode, DtwParameters, FullParams, WhisperContext, WhisperContextParameters, WhisperState};
+
+use crate::audio::{self, AudioOptions, ChannelMode};
+use crate::channels::{self, LevelMeter};
+use crate::chunk::{ChunkOptions, SampleSource, Stitcher};
+use crate::control::RunControl;
+use crate::decoding::DecodingOptions;
+use crate::diarize::{self, DiarizeOptions};
+use crate::error::{Result, SttError};
+use crate::prompt;
+use crate::resample::WHISPER_SAMPLE_RATE;
+use crate::vad::{SpeechDetector, VadMode, VadOptions};
+use crate::words::{self, DtwPreset, Word, WordTiming};
+
+/// One inference thread per physical core; hyper-threads add little to
+/// whisper.cpp's matrix kernels.
+pub fn default_threads() -> i32 {
+    num_cpus::get_physical().max(1) as i32
+}
+
+/// What the decoder produces from the audio.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Task {
+    /// Text in the spoken language.
+    #[default]
+    Transcribe,
+    /// English text, whatever the spoken language.
+    Translate,
+}
+
+impl std::str::FromStr for Task {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "transcribe" => Ok(Task::Transcribe),
+            "translate" => Ok(Task::Translate),
+            other => Err(format!("unknown task '{}' (expected transcribe or translate)", other)),
+        }
+    }
+}
+
+/// Decoding options for a single transcription run.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct TranscribeOptions {
+    /// Spoken language of the audio, e.g. `"en"`, or `"auto"` to detect it.
+    pub language: String,
+    /// Transcribe in the spoken language or translate into English.
+    pub task: Task,
+    /// Search strategy and temperature fallback.
+    pub decoding: DecodingOptions,
+    /// Number of inference threads; `None` uses one per physical core.
+    pub threads: Option<i32>,
+    /// Attach word-level timing and confidence to every segment.
+    pub word_timestamps: bool,
+    /// Channel selection and resampling applied when decoding files.
+    pub audio: AudioOptions,
+    /// Skip silence: only detected speech regions are decoded.
+    pub vad: VadOptions,
+    /// Window layout used when transcribing files and other sample sources.
+    pub chunking: ChunkOptions,
+    /// Text the decoder is conditioned on, e.g. a description of the call.
+    pub initial_prompt: Option<String>,
+    /// Names and jargon to bias the spelling towards.
+    pub vocabulary: Vec<String>,
+    /// Condition each chunk, region or stream step on the text before it.
+    pub carry_context: bool,
+    /// Label segments with speakers; not applied to live streams.
+    pub diarize: DiarizeOptions,
+    /// Names for the channels of [`ChannelMode::Separate`] transcription, in
+    /// channel order; unnamed channels are labelled `CHANNEL_<n>`.
+    pub channel_labels: Vec<String>,
+}
+
+impl Default for TranscribeOptions {
+    fn default() -> Self {
+        Self {
+            language: "en".to_string(),
+            task: Task::Transcribe,
+            decoding: DecodingOptions::default(),
+            threads: None,
+            word_timestamps: false,
+            audio: AudioOptions::default(),
+            vad: VadOptions::default(),
+            chunking: ChunkOptions::default(),
+            initial_prompt: None,
+            vocabulary: Vec::new(),
+            carry_context: false,
+            diarize: DiarizeOptions::default(),
+            channel_labels: Vec::new(),
+        }
+    }
+}
+
+impl TranscribeOptions {
+    /// The inference thread count after resolving `None` to the physical core count.
+    pub fn thread_count(&self) -> i32 {
+        self.threads.unwrap_or_else(default_threads)
+    }
+
+    /// The prompt for a decode preceded by `context`, which is only used
+    /// when [`TranscribeOptions::carry_context`] is set.
+    pub fn prompt(&self, context: Option<&str>) -> Option<String> {
+        let context = context.filter(|_| self.carry_context);
+        prompt::build_prompt(&self.vocabulary, self.initial_prompt.as_deref(), context)
+    }
+
+    /// Checks the options that do not depend on the model.
+    ///
+    /// Decoder candidate and beam counts must be within whisper.cpp's limit.
+    /// Language, prompt and vocabulary reach whisper.cpp as C strings, so an
+    /// embedded NUL is refused here rather than panicking in the conversion.
+    pub fn validate(&self) -> Result<()> {
+        self.decoding.validate()?;
+        let texts = [("language", self.language.as_str())]
+            .into_iter()
+            .chain(self.initial_prompt.as_deref().map(|prompt| ("prompt", prompt)))
+            .chain(self.vocabulary.iter().map(|term| ("vocabulary term", term.as_str())));
+        for (field, text) in texts {
+            if text.contains('\0') {
+                return Err(SttError::InvalidInput(format!("the {} contains a NUL character", field)));
+            }
+        }
+        Ok(())
+    }
+
+    /// Builds the whisper.cpp decoder parameters for these options.
+    pub fn to_full_params(&self) -> FullParams<'_, '_> {
+        let mut params = FullParams::new(self.decoding.sampling_strategy());
+        params.set_temperature(self.decoding.temperature);
+        params.set_temperature_inc(self.decoding.temperature_increment);
+        params.set_entropy_thold(self.decoding.entropy_threshold);
+        params.set_logprob_thold(self.decoding.logprob_threshold);
+        params.set_language(Some(&self.language));
+        params.set_translate(self.task == Task::Translate);
+        params.set_n_threads(self.thread_count());
+        params.set_token_timestamps(self.word_timestamps);
+        // whisper.cpp would otherwise prepend the previous decode's tokens kept on
+        // the (possibly reused) state; carried context goes in the explicit prompt.
+        params.set_no_context(true);
+        if let Some(prompt) = self.prompt(None) {
+            params.set_initial_prompt(&prompt);
+        }
+        params.set_print_progress(false);
+        params.set_print_realtime(false);
+        params.set_print_timestamps(false);
+        params.set_print_special(false);
+        params
+    }
+}
+
+/// A span of recognised speech.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Segment {
+    pub start_ms: i64,
+    pub end_ms: i64,
+    pub text: String,
+    /// Probability that the segment contains no speech.
+    pub no_speech_prob: f32,
+    /// Mean log-probability of the segment's text tokens.
+    pub avg_logprob: f32,
+    /// Word timing, present when word timestamps were requested.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub words: Vec<Word>,
+    /// Speaker label such as `"SPEAKER_00"`, present when diarizing.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<String>,
+    /// Zero-based source channel, present when channels were transcribed separately.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub channel: Option<u16>,
+    /// A tinydiarize model marked a change of speaker after this segment.
+    #[serde(skip)]
+    pub speaker_turn: bool,
+}
+
+impl Segment {
+    /// Moves the segment and its words `ms` milliseconds later on the timeline.
+    pub fn offset_by(&mut self, ms: i64) {
+        self.start_ms += ms;
+        self.end_ms += ms;
+        for word in &mut self.words {
+            word.start_ms += ms;
+            word.end_ms += ms;
+        }
+    }
+}
+
+/// The result of Whisper's language identification.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct DetectedLanguage {
+    /// Language code, e.g. `"de"`.
+    pub language: String,
+    pub probability: f32,
+}
+
+/// How a transcript was produced.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct RunInfo {
+    /// Path of the model that produced the transcript.
+    pub model: String,
+    #[serde(rename = "parameters")]
+    pub options: TranscribeOptions,
+    /// Set when the language was requested as `"auto"`.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detected_language: Option<DetectedLanguage>,
+}
+
+/// The result of a transcription run.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Transcript {
+    pub segments: Vec<Segment>,
+    pub run: RunInfo,
+}
+
+impl Transcript {
+    /// Collects the segments produced by the last `full` call on `state`,
+    /// merging tokens into words when `words` is set.
+    pub fn from_state(ctx: &WhisperContext, state: &WhisperState, words: Option<WordTiming>) -> Self {
+        // Ids at or above end-of-text are control and timestamp tokens.
+        let eot = ctx.token_eot();
+        let segments = state
+            .as_iter()
+            .map(|segment| {
+                let (sum, count) = (0..segment.n_tokens())
+                    .filter_map(|i| segment.get_token(i))
+                    .map(|token| token.token_data())
+                    .filter(|data| data.id < eot)
+                    .fold((0.0f32, 0u32), |(sum, count), data| (sum + data.plog, count + 1));
+                Segment {
+                    // whisper.cpp timestamps are in centiseconds
+                    start_ms: segment.start_timestamp() * 10,
+                    end_ms: segment.end_timestamp() * 10,
+                    text: segment.to_str_lossy().map(|t| t.trim().to_string()).unwrap_or_default(),
+                    no_speech_prob: segment.no_speech_probability(),
+                    avg_logprob: if count == 0 { 0.0 } else { sum / count as f32 },
+                    words: words.map(|timing| words::collect_words(&segment, eot, timing)).unwrap_or_default(),
+                    speaker: None,
+                    channel: None,
+                    speaker_turn: segment.next_segment_speaker_turn(),
+                }
+            })
+            .collect();
+        Self { segments, run: RunInfo::default() }
+    }
+
+    /// The full transcript text with segments separated by spaces.
+    pub fn text(&self) -> String {
+        self.segments.iter().map(|s| s.text.as_str()).collect::<Vec<_>>().join(" ")
+    }
+}
+
+/// A little over the one second whisper.cpp requires before it decodes anything.
+const MIN_DECODE_SAMPLES: usize = WHISPER_SAMPLE_RATE as usize * 105 / 100;
+
+/// Settings fixed when the model is loaded.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ModelOptions {
+    /// Alignment heads for DTW word timestamps; `None` uses token timestamps.
+    pub dtw: Option<DtwPreset>,
+}
+
+/// A loaded Whisper model that can transcribe any number of inputs.
+pub struct Transcriber {
+    ctx: WhisperContext,
+    model_path: PathBuf,
+    dtw: bool,
+    /// The model predicts speaker turns (tinydiarize, `*-tdrz.bin`).
+    tdrz: bool,
+}
+
+impl Transcriber {
+    /// Loads the ggml model at `model_path`.
+    pub fn new(model_path: impl AsRef<Path>) -> Result<Self> {
+        Self::with_options(model_path, &ModelOptions::default())
+    }
+
+    /// Loads the ggml model at `model_path` with load-time settings.
+    pub fn with_options(model_path: impl AsRef<Path>, options: &ModelOptions) -> Result<Self> {
+        let path = model_path.as_ref();
+        let model_error = |source: Box<dyn std::error::Error + Send + Sync>| SttError::ModelLoad { path: path.to_path_buf(), source };
+        if let Err(e) = path.metadata() {
+            return Err(model_error(e.into()));
+        }
+        let path_str = path.to_str().ok_or_else(|| model_error("model path is not valid UTF-8".into()))?;
+
+        let dtw = match options.dtw {
+            Some(DtwPreset::Auto) => {
+                let name = path.file_name().map(|n| n.to_string_lossy()).unwrap_or_default();
+                let preset = DtwPreset::from_model_name(&name).ok_or_else(|| SttError::InvalidInput(format!(
+                    "cannot infer a DTW preset from model name '{}'; pass one explicitly", name
+                )))?;
+                preset.to_whisper()
+            }
+            Some(preset) => preset.to_whisper(),
+            None => None,
+        };
+        let mut params = WhisperContextParameters::default();
+        if let Some(model_preset) = dtw.clone() {
+            params.dtw_parameters(DtwParameters { mode: DtwMode::ModelPreset { model_preset }, ..Default::default() });
+        }
+
+        let ctx = WhisperContext::new_with_params(path_str, params)
+            .map_err(|e| model_error(e.into()))?;
+        let tdrz = path.file_name().is_some_and(|n| n.to_string_lossy().contains("tdrz"));
+        Ok(Self { ctx, model_path: path.to_path_buf(), dtw: dtw.is_some(), tdrz })
+    }
+
+    /// Path the model was loaded from.
+    pub fn model_path(&self) -> &Path {
+        &self.model_path
+    }
+
+    /// The underlying whisper.cpp context.
+    pub fn context(&self) -> &WhisperContext {
+        &self.ctx
+    }
+
+    /// Transcribes 16 kHz mono samples.
+    pub fn transcribe(&self, samples: &[f32], options: &TranscribeOptions) -> Result<Transcript> {
+        let mut state = self.ctx.create_state()?;
+        self.transcribe_with_state(&mut state, samples, options)
+    }
+
+    /// Like [`Transcriber::transcribe`], reusing a state created from [`Transcriber::context`].
+    pub fn transcribe_with_state(&self, state: &mut WhisperState, samples: &[f32], options: &TranscribeOptions) -> Result<Transcript> {
+        let mut detector = SpeechDetector::new(&options.vad, Some(options.thread_count()))?;
+        let mut transcript = self.transcribe_with_context(state, samples, options, &mut detector, None, &RunControl::default())?;
+        if options.diarize.enabled {
+            let features = diarize::segment_features(samples, 0, &transcript.segments);
+            diarize::label_speakers(&mut transcript.segments, &features, self.tdrz, &options.diarize);
+        }
+        Ok(transcript)
+    }
+
+    /// Transcribes `samples` as the continuation of `context`, the text
+    /// transcribed just before them, skipping what `detector` finds silent.
+    pub(crate) fn transcribe_with_context(&self, state: &mut WhisperState, samples: &[f32], options: &TranscribeOptions, detector: &mut SpeechDetector, context: Option<&str>, control: &RunControl) -> Result<Transcript> {
+        let mut run = self.run_info(options);
+        let resolved;
+        let options = match self.resolve_language(state, samples, options)? {
+            Some(detected) => {
+                resolved = TranscribeOptions { language: detected.language.clone(), ..options.clone() };
+                run.detected_language = Some(detected);
+                &resolved
+            }
+            None => options,
+        };
+
+        if detector.mode() == VadMode::Off {
+            let segments = self.decode(state, samples, options, context, control)?;
+            return Ok(Transcript { segments, run });
+        }
+
+        let mut context = context.map(str::to_string);
+        let mut segments = Vec::new();
+        for region in detector.detect(samples)? {
+            let mut audio = samples[region.start..region.end].to_vec();
+            // whisper.cpp skips inputs shorter than one second; pad short regions with silence.
+            audio.resize(audio.len().max(MIN_DECODE_SAMPLES), 0.0);
+            let decoded = self.decode(state, &audio, options, context.as_deref(), control)?;
+            if options.carry_context && !decoded.is_empty() {
+                context = Some(decoded.iter().map(|s| s.text.as_str()).collect::<Vec<_>>().join(" "));
+            }
+            for mut segment in decoded {
+                segment.offset_by(region.start_ms());
+                segment.end_ms = segment.end_ms.min(region.end_ms());
+                segment.start_ms = segment.start_ms.min(segment.end_ms);
+                for word in &mut segment.words {
+                    word.end_ms = word.end_ms.min(segment.end_ms);
+                    word.start_ms = word.start_ms.min(word.end_ms);
+                }
+                segments.push(segment);
+            }
+        }
+        Ok(Transcript { segments, run })
+    }
+
+    /// Identifies the spoken language from the first 30 seconds of `samples`.
+    ///
+    /// English-only models always report English with probability 1.
+    pub fn detect_language(&self, state: &mut WhisperState, samples: &[f32], threads: i32) -> Result<DetectedLanguage> {
+        if !self.ctx.is_multilingual() {
+            return Ok(DetectedLanguage { language: "en".to_string(), probability: 1.0 });
+        }
+        let window = &samples[..samples.len().min(30 * WHISPER_SAMPLE_RATE as usize)];
+        state.pcm_to_mel(window, threads.max(1) as usize)?;
+        let (id, probabilities) = state.lang_detect(0, threads.max(1) as usize)?;
+        let language = whisper_rs::get_lang_str(id).unwrap_or("en").to_string();
+        let probability = probabilities.get(id as usize).copied().unwrap_or_default();
+        Ok(DetectedLanguage { language, probability })
+    }
+
+    /// Rejects invalid options (see [`TranscribeOptions::validate`]), unknown
+    /// language codes, and anything but English transcription on English-only models.
+    pub fn check_options(&self, options: &TranscribeOptions) -> Result<()> {
+        options.validate()?;
+        if options.task == Task::Translate && !self.ctx.is_multilingual() {
+            return Err(SttError::InvalidInput(format!(
+                "model '{}' is English-only and cannot translate; use a multilingual model (e.g. ggml-base.bin)",
+                self.model_path.display()
+            )));
+        }
+        let language = options.language.as_str();
+        if language == "auto" {
+            return Ok(());
+        }
+        if whisper_rs::get_lang_id(language).is_none() {
+            return Err(SttError::InvalidInput(format!("unknown language '{}'; use a Whisper language code such as 'en' or 'auto'", language)));
+        }
+        if language != "en" && !self.ctx.is_multilingual() {
+            return Err(SttError::InvalidInput(format!(
+                "model '{}' is English-only and cannot transcribe language '{}'; use a multilingual model (e.g. ggml-base.bin)",
+                self.model_path.display(), language
+            )));
+        }
+        Ok(())
+    }
+
+    /// Runs language detection when `options.language` is `"auto"`.
+    fn resolve_language(&self, state: &mut WhisperState, samples: &[f32], options: &TranscribeOptions) -> Result<Option<DetectedLanguage>> {
+        self.check_options(options)?;
+        if options.language != "auto" {
+            return Ok(None);
+        }
+        let detected = self.detect_language(state, samples, options.thread_count())?;
+        eprintln!("Detected language: {} (p = {:.2})", detected.language, detected.probability);
+        Ok(Some(detected))
+    }
+
+    fn run_info(&self, options: &TranscribeOptions) -> RunInfo {
+        RunInfo { model: self.model_path.display().to_string(), options: options.clone(), detected_language: None }
+    }
+
+    fn decode(&self, state: &mut WhisperState, samples: &[f32], options: &TranscribeOptions, context: Option<&str>, control: &RunControl) -> Result<Vec<Segment>> {
+        let mut params = options.to_full_params();
+        if context.is_some()
+            && let Some(prompt) = options.prompt(context)
+        {
+            params.set_initial_prompt(&prompt);
+        }
+        params.set_tdrz_enable(self.tdrz && options.diarize.enabled);
+        let abort = control.clone();
+        // whisper-rs only calls a boxed abort closure soundly.
+        params.set_abort_callback_safe(Box::new(move || abort.is_cancelled()) as Box<dyn FnMut() -> bool>);
+        if control.wants_progress() {
+            let progress = control.clone();
+            params.set_progress_callback_safe(move |percent| progress.report_percent(percent));
+        }
+        let decoded = state.full(params, samples);
+        if control.is_cancelled() {
+            return Err(SttError::Cancelled);
+        }
+        decoded?;
+        let timing = if self.dtw { WordTiming::Dtw } else { WordTiming::Tokens };
+        Ok(Transcript::from_state(&self.ctx, state, options.word_timestamps.then_some(timing)).segments)
+    }
+
+    /// Transcribes audio pulled from `source` one chunk at a time, so memory
+    /// use does not grow with the length of the input.
+    pub fn transcribe_chunked(&self, source: &mut dyn SampleSource, options: &TranscribeOptions) -> Result<Transcript> {
+        let mut state = self.ctx.create_state()?;
+        self.transcribe_chunked_with_state(&mut state, source, options)
+    }
+
+    /// Like [`Transcriber::transcribe_chunked`], reusing a state created from [`Transcriber::context`].
+    pub fn transcribe_chunked_with_state(&self, state: &mut WhisperState, source: &mut dyn SampleSource, options: &TranscribeOptions) -> Result<Transcript> {
+        self.transcribe_chunked_with_control(state, source, options, &RunControl::default())
+    }
+
+    /// Like [`Transcriber::transcribe_chunked_with_state`], reporting progress
+    /// to and stopping on request of `control`.
+    pub fn transcribe_chunked_with_control(&self, state: &mut WhisperState, source: &mut dyn SampleSource, options: &TranscribeOptions, control: &RunControl) -> Result<Transcript> {
+        self.check_options(options)?;
+        let to_ms = |samples: usize| (samples as u64 * 1000 / WHISPER_SAMPLE_RATE as u64) as i64;
+        control.set_total_ms(source.total_samples().map(to_ms));
+        let (chunk, overlap) = options.chunking.samples();
+        let step = chunk - overlap;
+        let mut run = self.run_info(options);
+        let mut resolved = options.clone();
+        let mut detector = SpeechDetector::new(&options.vad, Some(options.thread_count()))?;
+        let mut window: Vec<f32> = Vec::with_capacity(chunk);
+        let mut window_start = 0usize;
+        let mut stitcher = Stitcher::default();
+        let mut features = Vec::new();
+        let mut ended = false;
+
+        loop {
+            if control.is_cancelled() {
+                return Err(SttError::Cancelled);
+            }
+            while window.len() < chunk && !ended {
+                let more = source.read(chunk - window.len())?;
+                ended = more.is_empty();
+                window.extend_from_slice(&more);
+            }
+            if window.is_empty() {
+                break;
+            }
+
+            // Detect once, on the first chunk, so every chunk decodes the same language.
+            if window_start == 0
+                && let Some(detected) = self.resolve_language(state, &window, options)?
+            {
+                resolved.language = detected.language.clone();
+                run.detected_language = Some(detected);
+            }
+
+            let offset_ms = to_ms(window_start);
+            let context = resolved.carry_context.then(|| stitcher.recent_text(prompt::MAX_CONTEXT_CHARS));
+            control.begin_window(offset_ms, to_ms(window.len()));
+            let mut segments = self.transcribe_with_context(state, &window, &resolved, &mut detector, context.as_deref(), control)?.segments;
+            control.report(to_ms(window_start + window.len()));
+            for segment in &mut segments {
+                segment.offset_by(offset_ms);
+            }
+            // Cut in the middle of the overlap shared with the next chunk.
+            let cut = (window_start + step + overlap / 2) as u64 * 1000 / WHISPER_SAMPLE_RATE as u64;
+            let kept = stitcher.segments().len();
+            stitcher.push(segments, (!ended).then_some(cut as i64));
+            if options.diarize.enabled {
+                // Voice features need this chunk's audio, which the next step discards.
+                features.extend(diarize::segment_features(&window, offset_ms, &stitcher.segments()[kept..]));
+            }
+            if ended {
+                break;
+            }
+            window.drain(..step);
+            window_start += step;
+        }
+
+        let mut segments = stitcher.finish();
+        if options.diarize.enabled {
+            diarize::label_speakers(&mut segments, &features, self.tdrz, &options.diarize);
+        }
+        Ok(Transcript { segments, run })
+    }
+
+    /// Decodes the audio file at `path` and transcribes it chunk by chunk.
+    ///
+    /// With [`ChannelMode::Separate`] every channel is transcribed on its own
+    /// and the results are merged, labelled by channel, with crosstalk removed.
+    pub fn transcribe_file(&self, path: impl AsRef<Path>, options: &TranscribeOptions) -> Result<Transcript> {
+        let mut state = self.ctx.create_state()?;
+        self.transcribe_file_with_state(&mut state, path, options)
+    }
+
+    /// Like [`Transcriber::transcribe_file`], reusing a state created from [`Transcriber::context`].
+    pub fn transcribe_file_with_state(&self, state: &mut WhisperState, path: impl AsRef<Path>, options: &TranscribeOptions) -> Result<Transcript> {
+        self.transcribe_file_with_control(state, path, options, &RunControl::default())
+    }
+
+    /// Like [`Transcriber::transcribe_file_with_state`], reporting progress
+    /// to and stopping on request of `control`.
+    pub fn transcribe_file_with_control(&self, state: &mut WhisperState, path: impl AsRef<Path>, options: &TranscribeOptions, control: &RunControl) -> Result<Transcript> {
+        if options.audio.channels == ChannelMode::Separate {
+            let mut transcripts = Vec::new();
+            let sources = audio::open_channels(path, &options.audio)?;
+            let count = sources.len();
+            for (index, mut source) in sources.into_iter().enumerate() {
+                eprintln!("Transcribing channel {}", index);
+                control.begin_pass(count);
+                let mut meter = LevelMeter::new(source.as_mut());
+                let transcript = self.transcribe_chunked_with_control(state, &mut meter, options, control)?;
+                transcripts.push((transcript, meter.finish()));
+            }
+            return Ok(channels::merge(transcripts, &options.channel_labels));
+        }
+        let mut source = audio::open_audio(path, &options.audio)?;
+        self.transcribe_chunked_with_control(state, source.as_mut(), options, control)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn validate_rejects_nul_in_text_passed_to_whisper() {
+        assert!(TranscribeOptions::default().validate().is_ok());
+        let cases = [
+            TranscribeOptions { language: "\0".to_string(), ..Default::default() },
+            TranscribeOptions { initial_prompt: Some("a\0b".to_string()), ..Default::default() },
+            TranscribeOptions { vocabulary: vec!["ok".to_string(), "x\0".to_string()], ..Default::default() },
+        ];
+        for options in cases {
+            assert!(matches!(options.validate(), Err(SttError::InvalidInput(_))), "{options:?}");
+        }
+    }
+}
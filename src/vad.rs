@@ -0,0 +1,270 @@
+//! Voice activity detection.
+//!
+//! Splits 16 kHz mono audio into speech regions so silence never reaches the
+//! decoder. Two detectors are available: a frame-energy gate that needs no
+//! model, and whisper.cpp's Silero VAD, which needs a ggml Silero model.
+
+use std::path::PathBuf;
+
+use serde::Serialize;
+use whisper_rs::{WhisperVadContext, WhisperVadContextParams, WhisperVadParams};
+
+use crate::error::{Result, SttError};
+use crate::resample::WHISPER_SAMPLE_RATE;
+
+/// Which detector, if any, runs before inference.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum VadMode {
+    /// Transcribe the whole input.
+    #[default]
+    Off,
+    /// Frame RMS compared against an estimated noise floor.
+    Energy,
+    /// whisper.cpp's Silero VAD; requires [`VadOptions::model`].
+    Silero,
+}
+
+impl std::str::FromStr for VadMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "off" | "none" => Ok(VadMode::Off),
+            "energy" => Ok(VadMode::Energy),
+            "silero" => Ok(VadMode::Silero),
+            other => Err(format!("unknown VAD mode '{}' (expected off, energy or silero)", other)),
+        }
+    }
+}
+
+/// Voice activity detection settings.
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct VadOptions {
+    pub mode: VadMode,
+    /// ggml Silero VAD model, e.g. `ggml-silero-v5.1.2.bin`.
+    pub model: Option<PathBuf>,
+    /// Silero speech probability above which a frame counts as speech.
+    pub threshold: f32,
+    /// Energy detector: how far above the noise floor a frame must be, in dB.
+    pub energy_margin_db: f32,
+    /// Speech shorter than this is discarded as noise.
+    pub min_speech_ms: u32,
+    /// Pauses shorter than this do not end a region.
+    pub min_silence_ms: u32,
+    /// Audio kept on each side of a region so word edges are not clipped.
+    pub pad_ms: u32,
+}
+
+impl Default for VadOptions {
+    fn default() -> Self {
+        Self {
+            mode: VadMode::Off,
+            model: None,
+            threshold: 0.5,
+            energy_margin_db: 12.0,
+            min_speech_ms: 250,
+            min_silence_ms: 500,
+            pad_ms: 200,
+        }
+    }
+}
+
+/// A stretch of speech, as sample indices into the 16 kHz input.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SpeechRegion {
+    pub start: usize,
+    pub end: usize,
+}
+
+impl SpeechRegion {
+    pub fn start_ms(&self) -> i64 {
+        samples_to_ms(self.start)
+    }
+
+    pub fn end_ms(&self) -> i64 {
+        samples_to_ms(self.end)
+    }
+}
+
+fn samples_to_ms(samples: usize) -> i64 {
+    (samples as u64 * 1000 / WHISPER_SAMPLE_RATE as u64) as i64
+}
+
+fn ms_to_samples(ms: u32) -> usize {
+    (ms as u64 * WHISPER_SAMPLE_RATE as u64 / 1000) as usize
+}
+
+/// 30 ms analysis frames for the energy detector.
+const FRAME: usize = WHISPER_SAMPLE_RATE as usize * 30 / 1000;
+/// The noise floor is never assumed to be louder than this, so audio that is
+/// speech from start to finish is not mistaken for noise.
+const MAX_NOISE_FLOOR_DB: f32 = -45.0;
+
+/// Finds the speech regions of 16 kHz mono `samples`. With [`VadMode::Off`]
+/// the whole input is a single region.
+///
+/// Loads the Silero model on every call; use a [`SpeechDetector`] to run
+/// detection on many buffers.
+pub fn detect_speech(samples: &[f32], options: &VadOptions, threads: Option<i32>) -> Result<Vec<SpeechRegion>> {
+    SpeechDetector::new(options, threads)?.detect(samples)
+}
+
+/// A detector for one run, e.g. every chunk of a file or every step of a
+/// stream, so the Silero model is loaded once rather than per buffer.
+pub struct SpeechDetector {
+    options: VadOptions,
+    silero: Option<WhisperVadContext>,
+}
+
+impl SpeechDetector {
+    /// Loads the Silero model when `options` ask for it.
+    pub fn new(options: &VadOptions, threads: Option<i32>) -> Result<Self> {
+        let silero = match options.mode {
+            VadMode::Silero => Some(load_silero(options, threads)?),
+            VadMode::Off | VadMode::Energy => None,
+        };
+        Ok(Self { options: options.clone(), silero })
+    }
+
+    pub fn mode(&self) -> VadMode {
+        self.options.mode
+    }
+
+    /// Finds the speech regions of 16 kHz mono `samples`, as [`detect_speech`] does.
+    pub fn detect(&mut self, samples: &[f32]) -> Result<Vec<SpeechRegion>> {
+        if samples.is_empty() {
+            return Ok(Vec::new());
+        }
+        let raw = match (self.options.mode, &mut self.silero) {
+            (VadMode::Off, _) => return Ok(vec![SpeechRegion { start: 0, end: samples.len() }]),
+            (_, Some(vad)) => silero_regions(vad, samples, &self.options)?,
+            (_, None) => energy_regions(samples, &self.options),
+        };
+        Ok(clean_up(raw, samples.len(), &self.options))
+    }
+}
+
+fn energy_regions(samples: &[f32], options: &VadOptions) -> Vec<SpeechRegion> {
+    let levels: Vec<f32> = samples
+        .chunks(FRAME)
+        .map(|frame| {
+            let power = frame.iter().map(|s| s * s).sum::<f32>() / frame.len() as f32;
+            10.0 * power.max(1e-12).log10()
+        })
+        .collect();
+
+    let mut sorted = levels.clone();
+    sorted.sort_by(f32::total_cmp);
+    let floor = sorted[sorted.len() / 10].min(MAX_NOISE_FLOOR_DB);
+    let gate = floor + options.energy_margin_db;
+
+    let mut regions = Vec::new();
+    let mut open: Option<usize> = None;
+    for (i, level) in levels.iter().enumerate() {
+        match (open, *level > gate) {
+            (None, true) => open = Some(i * FRAME),
+            (Some(start), false) => {
+                regions.push(SpeechRegion { start, end: i * FRAME });
+                open = None;
+            }
+            _ => {}
+        }
+    }
+    if let Some(start) = open {
+        regions.push(SpeechRegion { start, end: samples.len() });
+    }
+    regions
+}
+
+fn load_silero(options: &VadOptions, threads: Option<i32>) -> Result<WhisperVadContext> {
+    let path = options.model.as_ref()
+        .ok_or_else(|| SttError::InvalidInput("Silero VAD needs a VAD model path".to_string()))?;
+    let model_error = |source: Box<dyn std::error::Error + Send + Sync>| SttError::ModelLoad { path: path.clone(), source };
+    if let Err(e) = path.metadata() {
+        return Err(model_error(e.into()));
+    }
+    let path_str = path.to_str().ok_or_else(|| model_error("model path is not valid UTF-8".into()))?;
+
+    let mut context_params = WhisperVadContextParams::default();
+    if let Some(threads) = threads {
+        context_params.set_n_threads(threads);
+    }
+    WhisperVadContext::new(path_str, context_params).map_err(|e| model_error(e.into()))
+}
+
+fn silero_regions(vad: &mut WhisperVadContext, samples: &[f32], options: &VadOptions) -> Result<Vec<SpeechRegion>> {
+    // Merging and padding happen in `clean_up`, identically for both detectors.
+    let mut params = WhisperVadParams::default();
+    params.set_threshold(options.threshold);
+    params.set_min_speech_duration(0);
+    params.set_min_silence_duration(0);
+    params.set_speech_pad(0);
+
+    // whisper.cpp reports VAD boundaries in centiseconds.
+    let to_sample = |cs: f32| ((cs.max(0.0) * (WHISPER_SAMPLE_RATE / 100) as f32) as usize).min(samples.len());
+    Ok(vad
+        .segments_from_samples(params, samples)?
+        .map(|segment| SpeechRegion { start: to_sample(segment.start), end: to_sample(segment.end) })
+        .filter(|region| region.end > region.start)
+        .collect())
+}
+
+/// Bridges short pauses, drops blips, then pads and re-merges.
+fn clean_up(raw: Vec<SpeechRegion>, len: usize, options: &VadOptions) -> Vec<SpeechRegion> {
+    let min_silence = ms_to_samples(options.min_silence_ms);
+    let min_speech = ms_to_samples(options.min_speech_ms);
+    let pad = ms_to_samples(options.pad_ms);
+
+    let mut bridged: Vec<SpeechRegion> = Vec::with_capacity(raw.len());
+    for region in raw {
+        match bridged.last_mut() {
+            Some(last) if region.start.saturating_sub(last.end) < min_silence => last.end = region.end.max(last.end),
+            _ => bridged.push(region),
+        }
+    }
+
+    let mut regions: Vec<SpeechRegion> = Vec::with_capacity(bridged.len());
+    for region in bridged.into_iter().filter(|r| r.end - r.start >= min_speech) {
+        let padded = SpeechRegion { start: region.start.saturating_sub(pad), end: (region.end + pad).min(len) };
+        match regions.last_mut() {
+            Some(last) if padded.start <= last.end => last.end = padded.end,
+            _ => regions.push(padded),
+        }
+    }
+    regions
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn region(start: usize, end: usize) -> SpeechRegion {
+        SpeechRegion { start, end }
+    }
+
+    #[test]
+    fn clean_up_bridges_pauses_drops_blips_and_pads() {
+        // Defaults at 16 samples per ms: bridge < 8000, keep >= 4000, pad 3200.
+        let raw = vec![region(16_000, 24_000), region(30_000, 40_000), region(60_000, 61_000), region(70_000, 80_000), region(155_000, 159_000)];
+        let regions = clean_up(raw, 160_000, &VadOptions::default());
+        assert_eq!(regions, vec![region(12_800, 43_200), region(66_800, 83_200), region(151_800, 160_000)]);
+    }
+
+    #[test]
+    fn clean_up_merges_regions_that_overlap_once_padded() {
+        let options = VadOptions { pad_ms: 500, ..Default::default() };
+        let regions = clean_up(vec![region(0, 10_000), region(18_000, 25_000)], 100_000, &options);
+        assert_eq!(regions, vec![region(0, 33_000)]);
+    }
+
+    #[test]
+    fn energy_detector_finds_a_tone_in_quiet_noise() {
+        let samples: Vec<f32> = (0..40_000)
+            .map(|i| if (8_000..24_000).contains(&i) { 0.5 * (i as f32 * 0.1).sin() } else { 1e-4 * (i as f32 * 1.7).sin() })
+            .collect();
+        let options = VadOptions { mode: VadMode::Energy, ..Default::default() };
+        let regions = detect_speech(&samples, &options, None).unwrap();
+        assert_eq!(regions, vec![region(7_680 - 3_200, 24_000 + 3_200)]);
+    }
+}
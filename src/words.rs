@@ -0,0 +1,187 @@
+//! Word-level timing assembled from Whisper's BPE tokens.
+//!
+//! Whisper emits sub-word tokens; a token whose text starts with a space opens
+//! a new word and every other token (including punctuation) extends the
+//! current one. Word times come either from whisper.cpp's token timestamps or,
+//! when the model was loaded with alignment heads, from its DTW alignment.
+
+use serde::Serialize;
+use whisper_rs::{DtwModelPreset, WhisperSegment, WhisperTokenId};
+
+/// A recognised word and its position in the audio.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Word {
+    pub text: String,
+    pub start_ms: i64,
+    pub end_ms: i64,
+    /// Mean probability of the word's tokens.
+    pub probability: f32,
+}
+
+/// Where word boundaries come from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WordTiming {
+    /// whisper.cpp's heuristic per-token timestamps.
+    Tokens,
+    /// DTW alignment over cross-attention heads; tighter but needs a preset.
+    Dtw,
+}
+
+/// Alignment-head presets for DTW timestamps, one per released model size.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum DtwPreset {
+    /// Pick the preset from the model file name, e.g. `ggml-base.en.bin`.
+    Auto,
+    TinyEn,
+    Tiny,
+    BaseEn,
+    Base,
+    SmallEn,
+    Small,
+    MediumEn,
+    Medium,
+    LargeV1,
+    LargeV2,
+    LargeV3,
+    LargeV3Turbo,
+}
+
+impl DtwPreset {
+    const NAMES: [(&'static str, DtwPreset); 12] = [
+        ("tiny.en", DtwPreset::TinyEn),
+        ("tiny", DtwPreset::Tiny),
+        ("base.en", DtwPreset::BaseEn),
+        ("base", DtwPreset::Base),
+        ("small.en", DtwPreset::SmallEn),
+        ("small", DtwPreset::Small),
+        ("medium.en", DtwPreset::MediumEn),
+        ("medium", DtwPreset::Medium),
+        ("large-v1", DtwPreset::LargeV1),
+        ("large-v2", DtwPreset::LargeV2),
+        ("large-v3-turbo", DtwPreset::LargeV3Turbo),
+        ("large-v3", DtwPreset::LargeV3),
+    ];
+
+    /// Infers the preset from a model file name such as `ggml-small.en-q5_0.bin`.
+    pub fn from_model_name(name: &str) -> Option<Self> {
+        let name = name.to_ascii_lowercase();
+        let name = name.strip_prefix("ggml-").unwrap_or(&name);
+        // Longest names first so "base.en" wins over "base".
+        let mut names = Self::NAMES;
+        names.sort_by_key(|(n, _)| std::cmp::Reverse(n.len()));
+        names.into_iter().find(|(n, _)| name.starts_with(n)).map(|(_, preset)| preset)
+    }
+
+    pub(crate) fn to_whisper(self) -> Option<DtwModelPreset> {
+        Some(match self {
+            DtwPreset::Auto => return None,
+            DtwPreset::TinyEn => DtwModelPreset::TinyEn,
+            DtwPreset::Tiny => DtwModelPreset::Tiny,
+            DtwPreset::BaseEn => DtwModelPreset::BaseEn,
+            DtwPreset::Base => DtwModelPreset::Base,
+            DtwPreset::SmallEn => DtwModelPreset::SmallEn,
+            DtwPreset::Small => DtwModelPreset::Small,
+            DtwPreset::MediumEn => DtwModelPreset::MediumEn,
+            DtwPreset::Medium => DtwModelPreset::Medium,
+            DtwPreset::LargeV1 => DtwModelPreset::LargeV1,
+            DtwPreset::LargeV2 => DtwModelPreset::LargeV2,
+            DtwPreset::LargeV3 => DtwModelPreset::LargeV3,
+            DtwPreset::LargeV3Turbo => DtwModelPreset::LargeV3Turbo,
+        })
+    }
+}
+
+impl std::str::FromStr for DtwPreset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.to_ascii_lowercase();
+        if s == "auto" {
+            return Ok(DtwPreset::Auto);
+        }
+        Self::NAMES
+            .into_iter()
+            .find(|(name, _)| *name == s)
+            .map(|(_, preset)| preset)
+            .ok_or_else(|| format!("unknown DTW preset '{}' (expected auto or a model name such as base.en)", s))
+    }
+}
+
+struct PendingWord {
+    bytes: Vec<u8>,
+    start_ms: i64,
+    end_ms: i64,
+    probability_sum: f32,
+    tokens: u32,
+}
+
+impl PendingWord {
+    fn finish(self) -> Option<Word> {
+        let text = String::from_utf8_lossy(&self.bytes).trim().to_string();
+        (!text.is_empty()).then(|| Word {
+            text,
+            start_ms: self.start_ms,
+            end_ms: self.end_ms,
+            probability: self.probability_sum / self.tokens as f32,
+        })
+    }
+}
+
+/// Merges the text tokens of `segment` into words.
+///
+/// `eot` is the end-of-text token id; everything at or above it is a control
+/// or timestamp token and carries no text.
+pub(crate) fn collect_words(segment: &WhisperSegment<'_>, eot: WhisperTokenId, timing: WordTiming) -> Vec<Word> {
+    let segment_start = segment.start_timestamp() * 10;
+    let segment_end = segment.end_timestamp() * 10;
+
+    let mut words = Vec::new();
+    let mut current: Option<PendingWord> = None;
+    for i in 0..segment.n_tokens() {
+        let Some(token) = segment.get_token(i) else { continue };
+        let data = token.token_data();
+        if data.id >= eot {
+            continue;
+        }
+        let Ok(bytes) = token.to_bytes() else { continue };
+
+        // whisper.cpp timestamps are in centiseconds; t_dtw is -1 when DTW is off.
+        let (t0, t1) = match timing {
+            WordTiming::Dtw if data.t_dtw >= 0 => (data.t_dtw * 10, data.t_dtw * 10),
+            _ => (data.t0 * 10, data.t1 * 10),
+        };
+
+        match &mut current {
+            Some(word) if !bytes.starts_with(b" ") => {
+                word.bytes.extend_from_slice(bytes);
+                word.end_ms = word.end_ms.max(t1);
+                word.probability_sum += data.p;
+                word.tokens += 1;
+            }
+            _ => {
+                words.extend(current.take().and_then(PendingWord::finish));
+                current = Some(PendingWord {
+                    bytes: bytes.to_vec(),
+                    start_ms: t0,
+                    end_ms: t1,
+                    probability_sum: data.p,
+                    tokens: 1,
+                });
+            }
+        }
+    }
+    words.extend(current.and_then(PendingWord::finish));
+
+    // DTW yields one instant per token, so a word lasts until the next one starts.
+    if timing == WordTiming::Dtw {
+        let next_starts: Vec<i64> = words.iter().skip(1).map(|w| w.start_ms).chain([segment_end]).collect();
+        for (word, next_start) in words.iter_mut().zip(next_starts) {
+            word.end_ms = word.end_ms.max(next_start);
+        }
+    }
+    for word in &mut words {
+        word.start_ms = word.start_ms.clamp(segment_start, segment_end);
+        word.end_ms = word.end_ms.clamp(word.start_ms, segment_end);
+    }
+    words
+}